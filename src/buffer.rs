@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod buffer;
 pub mod buffer_mgr;
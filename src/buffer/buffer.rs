@@ -1,81 +1,127 @@
+use std::sync::atomic::{AtomicI32, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
+
 use crate::{
+    error::DbResult,
     file::{block_id::BlockId, file_mgr::FileMgr, page::Page},
     logging::log_mgr::LogMgr,
 };
 
-#[derive(PartialEq, Clone, Debug)]
+// Which transaction last changed a page and the LSN of the latest logged
+// change to it. Set and read under the page's latch, so that a flush writes
+// the page out along with the marks that go with it.
+#[derive(Debug)]
+pub struct Modification {
+    tx_num: AtomicI32,
+    lsn: AtomicI64,
+}
+
+impl Modification {
+    fn new() -> Self {
+        Modification {
+            tx_num: AtomicI32::new(-1),
+            lsn: AtomicI64::new(-1),
+        }
+    }
+
+    pub fn set(&self, tx_num: i32, lsn: i64) {
+        self.tx_num.store(tx_num, Ordering::SeqCst);
+        if lsn >= 0 {
+            self.lsn.store(lsn, Ordering::SeqCst);
+        }
+    }
+
+    pub fn tx_num(&self) -> i32 {
+        self.tx_num.load(Ordering::SeqCst)
+    }
+
+    fn lsn(&self) -> i64 {
+        self.lsn.load(Ordering::SeqCst)
+    }
+
+    fn clear(&self) {
+        self.tx_num.store(-1, Ordering::SeqCst)
+    }
+}
+
+// A frame of the buffer pool. Its pins are counted by the buffer manager.
+#[derive(Debug)]
 pub struct Buffer {
-    fm: FileMgr,
-    lm: LogMgr,
-    contents: Page,
+    fm: Arc<FileMgr>,
+    lm: Arc<Mutex<LogMgr>>,
+    // latched on its own, so that readers of the page don't wait for each
+    // other or for whoever holds the frame
+    contents: Arc<RwLock<Page>>,
+    modification: Arc<Modification>,
     blk: Option<BlockId>,
-    pub pins: i32,
-    tx_num: i32,
-    lsn: i32,
 }
 
 impl Buffer {
-    pub fn new(fm: &mut FileMgr, lm: &mut LogMgr) -> Self {
-        let contents = Page::new(fm.block_size());
+    pub fn new(fm: Arc<FileMgr>, lm: Arc<Mutex<LogMgr>>) -> Self {
+        let contents = Arc::new(RwLock::new(Page::new(fm.block_size())));
         Buffer {
-            fm: fm.to_owned(),
-            lm: lm.to_owned(),
+            fm,
+            lm,
             contents,
+            modification: Arc::new(Modification::new()),
             blk: None,
-            pins: 0,
-            tx_num: -1,
-            lsn: -1,
         }
     }
 
-    pub fn contents(&mut self) -> Page {
-        self.contents.to_owned()
+    // The page under an exclusive latch.
+    pub fn contents(&mut self) -> RwLockWriteGuard<'_, Page> {
+        self.contents.write().unwrap()
+    }
+
+    // The latch on the page: shared to read it, exclusive to change it. It is
+    // held only for the access itself, unlike the locks of a transaction,
+    // and never while waiting for the frame.
+    pub fn latch(&self) -> Arc<RwLock<Page>> {
+        self.contents.clone()
+    }
+
+    // For a writer that holds the exclusive latch but not the frame.
+    pub fn modification(&self) -> Arc<Modification> {
+        self.modification.clone()
     }
 
     pub fn set_contents(&mut self, page: Page) {
-        self.contents = page
+        *self.contents() = page
     }
 
     pub fn block(&mut self) -> Option<BlockId> {
         self.blk.to_owned()
     }
 
-    pub fn set_modified(&mut self, tx_num: i32, lsn: i32) {
-        self.tx_num = tx_num;
-        if lsn >= 0 {
-            self.lsn = lsn;
-        }
-    }
-
-    pub fn is_pinned(&mut self) -> bool {
-        self.pins > 0
+    pub fn set_modified(&mut self, tx_num: i32, lsn: i64) {
+        self.modification.set(tx_num, lsn)
     }
 
     pub fn modifying_tx(&mut self) -> i32 {
-        self.tx_num
+        self.modification.tx_num()
     }
 
-    pub fn assign_to_block(&mut self, blk: &mut BlockId) {
-        Self::flush(self);
+    // If the old block can't be written out, the buffer keeps it; if the new
+    // one can't be read, the buffer is left holding no block.
+    pub fn assign_to_block(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        Self::flush(self)?;
+        self.blk = None;
+        self.fm.read(blk, &mut self.contents.write().unwrap())?;
         self.blk = Some(blk.to_owned());
-        self.fm.read(blk, &mut self.contents);
-        self.pins = 0;
+        Ok(())
     }
 
-    pub fn flush(&mut self) {
-        if self.tx_num >= 0 {
-            self.lm.flush_with_lsn(self.lsn.try_into().unwrap());
+    // The latch is taken first, so that no change is half made while the
+    // marks are read and the log is forced up to them.
+    pub fn flush(&mut self) -> DbResult<()> {
+        let mut page = self.contents.write().unwrap();
+        if self.modification.tx_num() >= 0 {
+            let lsn = self.modification.lsn();
+            self.lm.lock().unwrap().flush_with_lsn(lsn)?;
             self.fm
-                .write(&mut self.blk.as_mut().unwrap(), &mut self.contents);
-            self.tx_num -= 1;
+                .write_with_lsn(self.blk.as_mut().unwrap(), &mut page, lsn)?;
+            self.modification.clear();
         }
-    }
-
-    pub fn pin(&mut self) {
-        self.pins += 1
-    }
-
-    pub fn unpin(&mut self) {
-        self.pins -= 1
+        Ok(())
     }
 }
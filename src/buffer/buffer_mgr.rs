@@ -1,164 +1,328 @@
-use crate::file::page::Page;
-use std::process::Command;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::{
     buffer::buffer::Buffer,
+    error::{DbError, DbResult},
     file::{block_id::BlockId, file_mgr::FileMgr},
     logging::log_mgr::LogMgr,
 };
 
-#[derive(Clone)]
+// Which unpinned frame is given to a block that isn't in the pool. Frames
+// that never held a block go first under every policy but Naive.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReplacementPolicy {
+    // the first unpinned frame
+    Naive,
+    // the one whose block was read in the longest ago
+    Fifo,
+    // the one whose block was pinned the longest ago
+    Lru,
+    // the next one the hand reaches whose block wasn't pinned since the
+    // hand last passed it
+    Clock,
+    // the one whose K-th most recent pin is the oldest; blocks pinned fewer
+    // than K times go first, least recently pinned first
+    LruK(usize),
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct BufferStats {
+    // pins of a block already in the pool
+    pub hits: u64,
+    // pins that had to read the block in
+    pub misses: u64,
+    // misses that replaced another block
+    pub evictions: u64,
+}
+
+impl BufferStats {
+    pub fn hit_ratio(&self) -> f64 {
+        if self.hits + self.misses == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / (self.hits + self.misses) as f64
+    }
+}
+
+// One buffer manager is shared by every transaction of a database. A pin
+// that finds every frame pinned waits for an unpin, up to max_time.
+// The pool's state is never held across I/O or while waiting for a frame:
+// a frame is reserved first and its old block written out and the new one
+// read in afterwards.
 pub struct BufferMgr {
-    fm: FileMgr,
-    lm: LogMgr,
-    buffer_size: usize,
-    pub buffer_pool: Vec<Buffer>,
+    buffer_pool: Vec<Arc<Mutex<Buffer>>>,
+    max_time: AtomicU64,
+    policy: ReplacementPolicy,
+    // no buffer's own lock is taken while this is held
+    state: Mutex<PoolState>,
+    cond: Condvar,
+}
+
+struct PoolState {
+    // the frame each block in the pool is in; while a frame is loading, both
+    // the block it held and the one being read in map to it
+    page_table: HashMap<BlockId, usize>,
     available_num: i32,
-    max_time: u64,
+    // what the pool and the policy know about each frame of buffer_pool
+    frames: Vec<FrameInfo>,
+    // counts pins, to order them
+    clock: u64,
+    // where the Clock policy resumes
+    hand: usize,
+    stats: BufferStats,
 }
 
-struct BufferRes {
-    buffer: Option<Buffer>,
-    idx: usize,
+#[derive(Default)]
+struct FrameInfo {
+    // the block the frame holds
+    blk: Option<BlockId>,
+    pins: usize,
+    // reserved by the pin that is writing out its block and reading in another
+    loading: bool,
+    // when the current block was read in
+    loaded_at: u64,
+    // when the current block was pinned, the latest last; as many as the policy needs
+    pinned_at: VecDeque<u64>,
+    // Clock's reference bit
+    referenced: bool,
 }
 
 impl BufferMgr {
-    pub fn new(fm: &mut FileMgr, lm: &mut LogMgr, buffer_size: usize) -> Self {
+    pub fn new(fm: Arc<FileMgr>, lm: Arc<Mutex<LogMgr>>, buffer_size: usize) -> Self {
+        Self::with_policy(fm, lm, buffer_size, ReplacementPolicy::Naive)
+    }
+
+    pub fn with_policy(
+        fm: Arc<FileMgr>,
+        lm: Arc<Mutex<LogMgr>>,
+        buffer_size: usize,
+        policy: ReplacementPolicy,
+    ) -> Self {
         let mut buffer_pool = Vec::with_capacity(buffer_size);
         for _ in 0..buffer_size {
-            buffer_pool.push(Buffer::new(fm, lm));
+            buffer_pool.push(Arc::new(Mutex::new(Buffer::new(fm.clone(), lm.clone()))));
         }
         BufferMgr {
-            fm: fm.to_owned(),
-            lm: lm.to_owned(),
-            buffer_size,
             buffer_pool,
-            available_num: buffer_size.try_into().unwrap(),
-            max_time: 10000,
+            max_time: AtomicU64::new(10000),
+            policy,
+            state: Mutex::new(PoolState {
+                page_table: HashMap::new(),
+                available_num: buffer_size.try_into().unwrap(),
+                frames: (0..buffer_size).map(|_| FrameInfo::default()).collect(),
+                clock: 0,
+                hand: 0,
+                stats: BufferStats::default(),
+            }),
+            cond: Condvar::new(),
         }
     }
 
-    pub fn available(&mut self) -> i32 {
-        self.available_num
+    // How long a pin may wait for a frame before it gives up with BufferAbort.
+    pub fn set_max_time(&self, max_time: Duration) {
+        self.max_time
+            .store(max_time.as_millis().try_into().unwrap(), Ordering::SeqCst)
+    }
+
+    pub fn available(&self) -> i32 {
+        self.state.lock().unwrap().available_num
     }
 
-    pub fn flush_all(&mut self, tx_num: i32) {
-        for buffer in self.buffer_pool.iter_mut() {
+    pub fn policy(&self) -> ReplacementPolicy {
+        self.policy
+    }
+
+    pub fn stats(&self) -> BufferStats {
+        self.state.lock().unwrap().stats
+    }
+
+    pub fn reset_stats(&self) {
+        self.state.lock().unwrap().stats = BufferStats::default();
+    }
+
+    pub fn flush_all(&self, tx_num: i32) -> DbResult<()> {
+        for buffer in self.buffer_pool.iter() {
+            let mut buffer = buffer.lock().unwrap();
             if buffer.modifying_tx() == tx_num {
-                buffer.flush()
+                buffer.flush()?
             }
         }
+        Ok(())
     }
 
-    pub fn unpin(&mut self, buffer: &mut Buffer) {
-        buffer.unpin();
-        let finded_buffer_res = Self::find_existing_buffer(self, &mut buffer.block().unwrap());
-        // update buffer_pool
-        self.buffer_pool[finded_buffer_res.idx] = buffer.to_owned();
-        if !buffer.is_pinned() {
-            self.available_num += 1;
-            // notifyAll();
+    // Write out every modified buffer, whichever transaction changed it.
+    pub fn flush_all_buffers(&self) -> DbResult<()> {
+        for buffer in self.buffer_pool.iter() {
+            buffer.lock().unwrap().flush()?
         }
+        Ok(())
     }
 
-    pub fn pin(&mut self, blk: &mut BlockId) -> Result<Buffer, String> {
-        let start = SystemTime::now();
-        let since_the_epoch = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards");
-        let timestamp =
-            since_the_epoch.as_secs() * 1000 + since_the_epoch.subsec_nanos() as u64 / 1_000_000;
-        let mut buffer = Self::try_to_pin(self, blk);
-        while buffer == None && !Self::wait_too_long(self, timestamp) {
-            let mut child = Command::new("sleep").arg(10.to_string()).spawn().unwrap();
-            child.wait().unwrap();
-            buffer = Self::try_to_pin(self, blk)
+    pub fn unpin(&self, buffer: &Arc<Mutex<Buffer>>) {
+        let idx = self
+            .buffer_pool
+            .iter()
+            .position(|b| Arc::ptr_eq(b, buffer))
+            .unwrap();
+        let mut state = self.state.lock().unwrap();
+        state.frames[idx].pins -= 1;
+        if state.frames[idx].pins == 0 {
+            state.available_num += 1;
+            self.cond.notify_all();
         }
-        match buffer {
-            Some(buffer) => Ok(buffer),
-            None => Err("BufferAbortException".to_string()),
+    }
+
+    pub fn pin(&self, blk: &mut BlockId) -> DbResult<Arc<Mutex<Buffer>>> {
+        let deadline = Instant::now() + Duration::from_millis(self.max_time.load(Ordering::SeqCst));
+        let mut state = self.state.lock().unwrap();
+        loop {
+            match self.try_to_pin(&mut state, blk) {
+                Some((idx, false)) => return Ok(self.buffer_pool[idx].clone()),
+                Some((idx, true)) => {
+                    drop(state);
+                    return self.load(idx, blk);
+                }
+                None => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(DbError::BufferAbort);
+            }
+            state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
         }
     }
 
-    fn wait_too_long(&mut self, start_time: u64) -> bool {
-        let start = SystemTime::now();
-        let since_the_epoch = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards");
-        let timestamp =
-            since_the_epoch.as_secs() * 1000 + since_the_epoch.subsec_nanos() as u64 / 1_000_000;
-        timestamp - start_time > self.max_time
+    // Pins the frame holding blk, or reserves one for it that still has to
+    // be loaded, which the bool says. None while every frame is pinned or
+    // blk's frame is loading.
+    fn try_to_pin(&self, state: &mut PoolState, blk: &BlockId) -> Option<(usize, bool)> {
+        state.clock += 1;
+        if let Some(idx) = Self::find_existing_buffer(state, blk) {
+            if state.frames[idx].loading {
+                return None;
+            }
+            state.stats.hits += 1;
+            self.record_pin(state, idx);
+            return Some((idx, false));
+        }
+        let idx = self.choose_unpinned_buffer(state)?;
+        state.page_table.insert(blk.to_owned(), idx);
+        // the old block stays where it is found until it has been written out
+        let old = state.frames[idx].blk.take();
+        state.frames[idx] = FrameInfo {
+            blk: old,
+            loading: true,
+            loaded_at: state.clock,
+            ..Default::default()
+        };
+        self.record_pin(state, idx);
+        Some((idx, true))
     }
 
-    fn try_to_pin(&mut self, blk: &mut BlockId) -> Option<Buffer> {
-        let mut found_buffer_res = Self::find_existing_buffer(self, blk);
-        match found_buffer_res.buffer {
-            Some(ref mut buffer) => {
-                if !buffer.is_pinned() {
-                    self.available_num -= 1;
+    // The I/O for a frame try_to_pin reserved, with the pool's state released.
+    // The page table only changes once the frame really changed hands.
+    fn load(&self, idx: usize, blk: &mut BlockId) -> DbResult<Arc<Mutex<Buffer>>> {
+        let buffer = self.buffer_pool[idx].clone();
+        let (res, held) = {
+            let mut b = buffer.lock().unwrap();
+            let res = b.assign_to_block(blk);
+            (res, b.block())
+        };
+        let mut state = self.state.lock().unwrap();
+        let old = state.frames[idx].blk.take();
+        state.frames[idx].loading = false;
+        self.cond.notify_all();
+        match res {
+            Ok(()) => {
+                if let Some(old) = old {
+                    state.page_table.remove(&old);
+                    state.stats.evictions += 1;
                 }
-                buffer.pin();
-                // update buffer_pool
-                self.buffer_pool[found_buffer_res.idx] = buffer.to_owned();
-                return Some(buffer.to_owned());
+                state.stats.misses += 1;
+                state.frames[idx].blk = held;
+                Ok(buffer)
             }
-            None => {
-                let mut choosed_buffer_res = Self::choose_unpinned_buffer(self);
-                match choosed_buffer_res.buffer {
-                    Some(ref mut buffer) => {
-                        buffer.assign_to_block(blk);
-                        // if blk file don't exist, contents of buffer will be reset when assign_to_block -> fm.read -> page.set_buffer
-                        if buffer.contents().buffer.len() == 0 {
-                            buffer.set_contents(Page::new(self.fm.block_size()))
-                        }
-                        if !buffer.is_pinned() {
-                            self.available_num -= 1;
-                        }
-                        buffer.pin();
-                        // update buffer_pool
-                        self.buffer_pool[choosed_buffer_res.idx] = buffer.to_owned();
-                        return Some(buffer.to_owned());
+            Err(err) => {
+                state.page_table.remove(blk);
+                if held.is_none() {
+                    if let Some(old) = old {
+                        state.page_table.remove(&old);
                     }
-                    None => return None,
-                };
+                    state.frames[idx] = FrameInfo::default();
+                } else {
+                    state.frames[idx].blk = held;
+                    state.frames[idx].pins = 0;
+                }
+                state.available_num += 1;
+                Err(err)
             }
+        }
+    }
+
+    fn record_pin(&self, state: &mut PoolState, idx: usize) {
+        let history = match self.policy {
+            ReplacementPolicy::LruK(k) => k.max(1),
+            _ => 1,
         };
+        let clock = state.clock;
+        let frame = &mut state.frames[idx];
+        if frame.pins == 0 {
+            state.available_num -= 1;
+        }
+        frame.pins += 1;
+        frame.pinned_at.push_back(clock);
+        if frame.pinned_at.len() > history {
+            frame.pinned_at.pop_front();
+        }
+        frame.referenced = true;
     }
 
-    fn find_existing_buffer(&mut self, blk: &mut BlockId) -> BufferRes {
-        for (i, buffer) in self.buffer_pool.iter_mut().enumerate() {
-            let b = buffer.block();
-            match b {
-                Some(b) => {
-                    if b.equals(blk) {
-                        return BufferRes {
-                            buffer: Some(buffer.to_owned()),
-                            idx: i,
-                        };
-                    }
+    fn find_existing_buffer(state: &PoolState, blk: &BlockId) -> Option<usize> {
+        state.page_table.get(blk).copied()
+    }
+
+    fn choose_unpinned_buffer(&self, state: &mut PoolState) -> Option<usize> {
+        let frames = &state.frames;
+        let unpinned: Vec<usize> = (0..frames.len()).filter(|i| frames[*i].pins == 0).collect();
+        let last_pinned = |i: &usize| frames[*i].pinned_at.back().copied().unwrap_or(0);
+        match self.policy {
+            ReplacementPolicy::Naive => unpinned.first().copied(),
+            ReplacementPolicy::Fifo => unpinned
+                .iter()
+                .copied()
+                .min_by_key(|i| frames[*i].loaded_at),
+            ReplacementPolicy::Lru => unpinned.iter().copied().min_by_key(last_pinned),
+            ReplacementPolicy::LruK(k) => unpinned.iter().copied().min_by_key(|i| {
+                let pinned_at = &frames[*i].pinned_at;
+                if pinned_at.len() < k.max(1) {
+                    (false, last_pinned(i))
+                } else {
+                    (true, pinned_at[0])
                 }
-                None => {}
-            };
+            }),
+            ReplacementPolicy::Clock => Self::sweep(state, !unpinned.is_empty()),
         }
-        return BufferRes {
-            buffer: None,
-            idx: 1000000,
-        };
     }
 
-    fn choose_unpinned_buffer(&mut self) -> BufferRes {
-        for (i, buffer) in self.buffer_pool.iter_mut().enumerate() {
-            if !buffer.is_pinned() {
-                return BufferRes {
-                    buffer: Some(buffer.to_owned()),
-                    idx: i.try_into().unwrap(),
-                };
-            }
+    // Moves the hand past referenced frames, clearing their bits, up to the
+    // first unpinned frame that isn't; at most two turns are needed.
+    fn sweep(state: &mut PoolState, any_unpinned: bool) -> Option<usize> {
+        if !any_unpinned {
+            return None;
         }
-        BufferRes {
-            buffer: None,
-            idx: 1000000,
+        loop {
+            let i = state.hand;
+            state.hand = (state.hand + 1) % state.frames.len();
+            if state.frames[i].pins > 0 {
+                continue;
+            }
+            if !state.frames[i].referenced {
+                return Some(i);
+            }
+            state.frames[i].referenced = false;
         }
     }
 }
@@ -174,52 +338,79 @@ mod tests {
             fs::remove_dir_all(db_dir).unwrap();
         }
         let block_size = 400;
-        let mut fm = FileMgr::new(db_dir.to_string(), block_size);
+        let fm = Arc::new(FileMgr::new(db_dir.to_string(), block_size).unwrap());
         let log_file = "ruspledb.log";
-        let mut lm = LogMgr::new(&mut fm, &mut log_file.to_string());
+        let lm = Arc::new(Mutex::new(
+            LogMgr::new(fm.clone(), &mut log_file.to_string()).unwrap(),
+        ));
         let buffer_size = 3;
-        let bm = BufferMgr::new(&mut fm, &mut lm, buffer_size);
-        bm
+        BufferMgr::new(fm, lm, buffer_size)
     }
 
     #[test]
     fn unit_find_existing_buffer() {
-        let mut bm = prepare_bm("./db/buffermgrunittest_1");
+        let bm = prepare_bm("./db/buffermgrunittest_1");
         let mut b0 = BlockId::new("testfile".to_string(), 0);
-        let buffer_res = bm.find_existing_buffer(&mut b0);
-        assert_eq!(buffer_res.buffer, None);
-        assert_eq!(buffer_res.idx, 1000000);
-        assert_eq!(bm.available(), 3);
+        {
+            let mut state = bm.state.lock().unwrap();
+            assert_eq!(BufferMgr::find_existing_buffer(&state, &b0), None);
+            assert_eq!(state.available_num, 3);
+            assert_eq!(bm.try_to_pin(&mut state, &b0), Some((0, true)));
+            // found while it loads, so nobody reads it in twice
+            assert_eq!(BufferMgr::find_existing_buffer(&state, &b0), Some(0));
+        }
+        let buffer = bm.load(0, &mut b0).unwrap();
+
+        // block 1 replaces block 0 in the same frame
+        bm.unpin(&buffer);
+        let mut b1 = BlockId::new("testfile".to_string(), 1);
+        assert_eq!(
+            bm.try_to_pin(&mut bm.state.lock().unwrap(), &b1),
+            Some((0, true))
+        );
+        // block 0 is still found until it has been written out
+        assert_eq!(
+            BufferMgr::find_existing_buffer(&bm.state.lock().unwrap(), &b0),
+            Some(0)
+        );
+        bm.load(0, &mut b1).unwrap();
+        let state = bm.state.lock().unwrap();
+        assert_eq!(BufferMgr::find_existing_buffer(&state, &b0), None);
+        assert_eq!(BufferMgr::find_existing_buffer(&state, &b1), Some(0));
     }
 
     #[test]
     fn unit_choose_unpined_buffer() {
-        let mut bm = prepare_bm("./db/buffermgrunittest_2");
-        let mut buffer_res = bm.choose_unpinned_buffer();
-        assert_eq!(buffer_res.buffer.as_mut().unwrap().block(), None);
-        assert_eq!(buffer_res.idx, 0);
+        let bm = prepare_bm("./db/buffermgrunittest_2");
+        let mut state = bm.state.lock().unwrap();
+        let idx = bm.choose_unpinned_buffer(&mut state).unwrap();
+        assert_eq!(idx, 0);
+        assert_eq!(bm.buffer_pool[idx].lock().unwrap().block(), None);
 
-        assert_eq!(bm.available(), 3);
+        assert_eq!(state.available_num, 3);
     }
 
     #[test]
     fn unit_try_to_pin() {
-        let mut bm = prepare_bm("./db/buffermgrunittest_3");
+        let bm = prepare_bm("./db/buffermgrunittest_3");
         let mut b0 = BlockId::new("testfile".to_string(), 0);
-        let mut buffer = bm.try_to_pin(&mut b0);
+        {
+            let mut state = bm.state.lock().unwrap();
+            assert_eq!(bm.try_to_pin(&mut state, &b0), Some((0, true)));
+            // a second pin waits for the first to load the block
+            assert_eq!(bm.try_to_pin(&mut state, &b0), None);
+        }
+        let buffer = bm.load(0, &mut b0).unwrap();
         assert_eq!(
-            buffer.as_mut().unwrap().block().unwrap().file_name(),
+            buffer.lock().unwrap().block().unwrap().file_name(),
             "testfile"
         );
-        assert_eq!(bm.available(), 2);
-        assert_eq!(buffer.as_mut().unwrap().pins, 1);
+        let mut state = bm.state.lock().unwrap();
+        assert_eq!(state.available_num, 2);
+        assert_eq!(state.frames[0].pins, 1);
 
-        let mut buffer = bm.try_to_pin(&mut b0);
-        assert_eq!(
-            buffer.as_mut().unwrap().block().unwrap().file_name(),
-            "testfile"
-        );
-        assert_eq!(bm.available(), 2);
-        assert_eq!(buffer.unwrap().pins, 2);
+        assert_eq!(bm.try_to_pin(&mut state, &b0), Some((0, false)));
+        assert_eq!(state.available_num, 2);
+        assert_eq!(state.frames[0].pins, 2);
     }
 }
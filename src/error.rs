@@ -0,0 +1,72 @@
+use std::fmt;
+use std::io;
+
+use crate::file::block_id::BlockId;
+
+// The errors every layer of the engine hands back to its caller. After a
+// BufferAbort, LockAbort or WriteConflict returned by a transaction, that
+// transaction has already been rolled back, unless it was rolling back or
+// recovering when the error came up. Rolling it back again does nothing;
+// anything else asked of it fails with Finished.
+#[derive(Debug)]
+pub enum DbError {
+    Io(io::Error),
+    BufferAbort,
+    LockAbort,
+    WriteConflict,
+    // bytes on disk that can't be what was written there
+    Corruption(String),
+    // a field the layout or schema doesn't have
+    SchemaMismatch(String),
+    // a value written past the end of its block
+    Overflow(String),
+    NoSavepoint(String),
+    NotPrepared(i32),
+    // a change asked of a transaction that has already prepared
+    AlreadyPrepared(i32),
+    // a transaction used after it committed or rolled back
+    Finished(i32),
+    // a block read or written without being pinned first
+    NotPinned(BlockId),
+    // a database opened with settings other than the ones it was created with
+    HeaderMismatch(String),
+}
+
+pub type DbResult<T> = Result<T, DbError>;
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Io(err) => write!(f, "io error: {err}"),
+            DbError::BufferAbort => write!(f, "BufferAbortException"),
+            DbError::LockAbort => write!(f, "LockAbortException"),
+            DbError::WriteConflict => write!(f, "WriteConflictException"),
+            DbError::Corruption(msg) => write!(f, "corrupted data: {msg}"),
+            DbError::SchemaMismatch(msg) => write!(f, "schema mismatch: {msg}"),
+            DbError::Overflow(msg) => write!(f, "page overflow: {msg}"),
+            DbError::NoSavepoint(name) => write!(f, "no savepoint named {name}"),
+            DbError::NotPrepared(tx_num) => write!(f, "transaction {tx_num} is not prepared"),
+            DbError::AlreadyPrepared(tx_num) => {
+                write!(f, "transaction {tx_num} is already prepared")
+            }
+            DbError::Finished(tx_num) => write!(f, "transaction {tx_num} has already finished"),
+            DbError::NotPinned(blk) => write!(f, "{blk} is not pinned"),
+            DbError::HeaderMismatch(msg) => write!(f, "database header mismatch: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(err: io::Error) -> Self {
+        DbError::Io(err)
+    }
+}
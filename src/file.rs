@@ -1,3 +1,5 @@
 pub mod block_id;
+pub mod crc32c;
+pub mod db_header;
 pub mod file_mgr;
 pub mod page;
@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Clone, PartialEq, Debug, Eq, Hash)]
 pub struct BlockId {
     file_name: String,
@@ -21,3 +23,9 @@ impl BlockId {
         self.file_name.eq(blk.file_name()) && self.blk_num.eq(&blk.blk_num)
     }
 }
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[file {}, block {}]", self.file_name, self.blk_num)
+    }
+}
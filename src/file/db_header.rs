@@ -0,0 +1,122 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytebuffer::ByteBuffer;
+
+use crate::error::{DbError, DbResult};
+use crate::file::crc32c::crc32c;
+use crate::file::page::{Page, Timestamp, I32_SIZE, I64_SIZE};
+
+// The file in the database directory that describes how the rest of it is
+// laid out. It isn't made of blocks, so it is never read through a page.
+pub const HEADER_FILE: &str = "dbheader";
+pub const MAGIC: &str = "RSPLEDB";
+const MAGIC_SIZE: usize = 8;
+// bumped whenever the on-disk format of any file changes
+pub const FORMAT_VERSION: i32 = 2;
+
+// feature flags
+pub const FLAG_CHECKSUMS: i32 = 1;
+const KNOWN_FLAGS: i32 = FLAG_CHECKSUMS;
+
+// magic, version, block size, creation time, flags, then the CRC-32C of all of it
+const VERSION_POS: usize = MAGIC_SIZE;
+const BLOCK_SIZE_POS: usize = VERSION_POS + I32_SIZE;
+const CREATED_POS: usize = BLOCK_SIZE_POS + I64_SIZE;
+const FLAGS_POS: usize = CREATED_POS + I64_SIZE;
+const CRC_POS: usize = FLAGS_POS + I32_SIZE;
+pub const HEADER_SIZE: usize = CRC_POS + I32_SIZE;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DbHeader {
+    pub version: i32,
+    pub block_size: u64,
+    pub created: Timestamp,
+    pub flags: i32,
+}
+
+impl DbHeader {
+    pub fn new(block_size: u64, flags: i32) -> Self {
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as i64);
+        DbHeader {
+            version: FORMAT_VERSION,
+            block_size,
+            created: Timestamp(micros),
+            flags,
+        }
+    }
+
+    pub fn has_checksums(&self) -> bool {
+        self.flags & FLAG_CHECKSUMS != 0
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut page = Page::new(HEADER_SIZE as u64);
+        page.set_fixed_string(0, MAGIC, MAGIC_SIZE).unwrap();
+        page.set_i32(VERSION_POS, self.version).unwrap();
+        page.set_u64(BLOCK_SIZE_POS, self.block_size).unwrap();
+        page.set_timestamp(CREATED_POS, self.created).unwrap();
+        page.set_i32(FLAGS_POS, self.flags).unwrap();
+        let crc = crc32c(&page.buffer.as_bytes()[..CRC_POS]);
+        page.set_i32(CRC_POS, crc as i32).unwrap();
+        page.buffer.into_vec()
+    }
+
+    pub fn decode(bytes: &[u8]) -> DbResult<Self> {
+        let page = Page::new_from_buffer(&mut ByteBuffer::from_vec(bytes.to_vec()));
+        if bytes.len() != HEADER_SIZE
+            || page.get_fixed_string(0, MAGIC_SIZE).ok().as_deref() != Some(MAGIC)
+        {
+            return Err(DbError::Corruption(format!(
+                "{HEADER_FILE} is not a database header"
+            )));
+        }
+        let crc = page.get_i32(CRC_POS)? as u32;
+        if crc != crc32c(&bytes[..CRC_POS]) {
+            return Err(DbError::Corruption(format!(
+                "{HEADER_FILE} fails its checksum"
+            )));
+        }
+        Ok(DbHeader {
+            version: page.get_i32(VERSION_POS)?,
+            block_size: page.get_u64(BLOCK_SIZE_POS)?,
+            created: page.get_timestamp(CREATED_POS)?,
+            flags: page.get_i32(FLAGS_POS)?,
+        })
+    }
+
+    // Whether a database with this header can be opened with the given
+    // block size and flags.
+    pub fn check(&self, block_size: u64, flags: i32) -> DbResult<()> {
+        if self.version != FORMAT_VERSION {
+            return Err(DbError::HeaderMismatch(format!(
+                "format version {}, expected {FORMAT_VERSION}",
+                self.version
+            )));
+        }
+        if self.flags & !KNOWN_FLAGS != 0 {
+            return Err(DbError::HeaderMismatch(format!(
+                "unknown feature flags {:#x}",
+                self.flags & !KNOWN_FLAGS
+            )));
+        }
+        if self.block_size != block_size {
+            return Err(DbError::HeaderMismatch(format!(
+                "block size {}, opened with {block_size}",
+                self.block_size
+            )));
+        }
+        if self.flags != flags {
+            let expected = if self.has_checksums() {
+                "with"
+            } else {
+                "without"
+            };
+            return Err(DbError::HeaderMismatch(format!(
+                "created {expected} checksums"
+            )));
+        }
+        Ok(())
+    }
+}
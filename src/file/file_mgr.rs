@@ -1,103 +1,516 @@
+use crate::error::{DbError, DbResult};
 use crate::file::block_id::BlockId;
+use crate::file::crc32c::crc32c;
+use crate::file::db_header::{DbHeader, FLAG_CHECKSUMS, HEADER_FILE};
 use crate::file::page::Page;
 use bytebuffer::ByteBuffer;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::Seek;
-use std::io::SeekFrom;
+use std::io;
 use std::io::Write;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-#[derive(Clone, PartialEq, Debug)]
+// O_DIRECT transfers must start at an aligned address and offset and span
+// whole aligned units.
+pub const DIRECT_IO_ALIGN: usize = 4096;
+pub const MAX_OPEN_FILES: usize = 64;
+// Scratch files for materialization and sorting, named the prefix followed
+// by a number; whatever is left of them when the database is opened again
+// is removed.
+pub const TEMP_PREFIX: &str = "temp";
+// With checksums, every block on disk starts with a header holding the CRC-32C
+// of the rest of the block (4 bytes), 4 reserved bytes and the LSN of the
+// latest log record that changed the block (8 bytes). The page follows it.
+pub const PAGE_HEADER_SIZE: u64 = 16;
+
+// When the writes to a file are forced to stable storage.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SyncPolicy {
+    // after every write
+    Always,
+    // the next time the log is forced, i.e. by the commit that needs them
+    OnCommit,
+    // left to the operating system
+    Never,
+}
+
+#[derive(Debug)]
 pub struct FileMgr {
     pub db_dir: String,
     pub block_size: u64,
+    checksums: bool,
+    is_new: bool,
+    header: DbHeader,
+    next_temp: AtomicU64,
+    default_sync_policy: Mutex<SyncPolicy>,
+    sync_policies: Mutex<HashMap<String, SyncPolicy>>,
+    // files written under OnCommit since they were last synced
+    unsynced: Mutex<HashSet<String>>,
+    // whether files were created, renamed or removed since the directory was last synced
+    dir_changed: AtomicBool,
+    direct_io: AtomicBool,
+    handles: Mutex<HandleCache>,
+}
+
+// The open files, with the least recently used one closed first once
+// `capacity` are open.
+#[derive(Debug)]
+struct HandleCache {
+    files: HashMap<String, (Arc<File>, u64)>,
+    capacity: usize,
+    clock: u64,
 }
 
 impl FileMgr {
-    pub fn new(db_dir: String, block_size: u64) -> Self {
-        if Self::is_new(&db_dir) {
-            fs::create_dir_all(&db_dir).unwrap();
-        }
-        FileMgr { db_dir, block_size }
+    pub fn new(db_dir: String, block_size: u64) -> DbResult<Self> {
+        Self::open(db_dir, block_size, false)
     }
 
-    pub fn read(&mut self, blk: &mut BlockId, page: &mut Page) {
-        let file_path = Path::new(&self.db_dir).join(blk.file_name());
-        let mut file = match Self::is_new(file_path.to_str().unwrap()) {
-            true => OpenOptions::new()
-                .write(true)
-                .read(true)
-                .create_new(true)
-                .open(file_path)
-                .unwrap(),
-            false => OpenOptions::new().read(true).open(file_path).unwrap(),
-        };
-        file.seek(SeekFrom::Start(blk.blk_num() * self.block_size))
-            .unwrap();
-        let mut buf_reader = BufReader::new(file);
-        let buf = buf_reader.fill_buf().unwrap();
-        page.set_buffer(ByteBuffer::from_bytes(buf));
+    // Blocks carry a checksummed header, so that a torn or corrupted block is
+    // reported by `read` instead of being decoded. A database has to be
+    // opened the same way every time.
+    pub fn with_checksums(db_dir: String, block_size: u64) -> DbResult<Self> {
+        Self::open(db_dir, block_size, true)
     }
 
-    pub fn write(&mut self, blk: &mut BlockId, page: &mut Page) {
-        let file_path = Path::new(&self.db_dir).join(blk.file_name());
-        let mut file = match Self::is_new(file_path.to_str().unwrap()) {
-            true => OpenOptions::new()
-                .write(true)
-                .read(true)
-                .create_new(true)
-                .open(file_path)
-                .unwrap(),
-            false => OpenOptions::new().write(true).open(file_path).unwrap(),
+    // Opens a database with the block size and features it was created with.
+    pub fn open_existing(db_dir: String) -> DbResult<Self> {
+        let header = DbHeader::decode(&fs::read(Path::new(&db_dir).join(HEADER_FILE))?)?;
+        Self::open(db_dir, header.block_size, header.has_checksums())
+    }
+
+    fn open(db_dir: String, block_size: u64, checksums: bool) -> DbResult<Self> {
+        if block_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "block size 0").into());
+        }
+        let flags = if checksums { FLAG_CHECKSUMS } else { 0 };
+        let is_new = !Path::new(&db_dir).exists();
+        if is_new {
+            fs::create_dir_all(&db_dir)?;
+        }
+        let header_path = Path::new(&db_dir).join(HEADER_FILE);
+        // only an empty directory gets a header like a new database; data files
+        // without one were written in a format this version can't read
+        let has_header = header_path.exists();
+        let header = if has_header {
+            let header = DbHeader::decode(&fs::read(&header_path)?)?;
+            header.check(block_size, flags)?;
+            header
+        } else if !is_new && has_data_files(&db_dir)? {
+            return Err(DbError::HeaderMismatch(
+                "missing database header".to_string(),
+            ));
+        } else {
+            DbHeader::new(block_size, flags)
         };
-        file.seek(SeekFrom::Start(blk.blk_num() * self.block_size))
-            .unwrap();
-        file.write_all(page.contents().as_bytes()).unwrap();
+        let fm = FileMgr {
+            db_dir,
+            block_size,
+            checksums,
+            is_new,
+            header,
+            next_temp: AtomicU64::new(0),
+            default_sync_policy: Mutex::new(SyncPolicy::OnCommit),
+            sync_policies: Mutex::new(HashMap::new()),
+            unsynced: Mutex::new(HashSet::new()),
+            dir_changed: AtomicBool::new(false),
+            direct_io: AtomicBool::new(false),
+            handles: Mutex::new(HandleCache {
+                files: HashMap::new(),
+                capacity: MAX_OPEN_FILES,
+                clock: 0,
+            }),
+        };
+        if !has_header {
+            let mut file = File::create(&header_path)?;
+            file.write_all(&fm.header.encode())?;
+            file.sync_all()?;
+            fm.dir_changed.store(true, Ordering::SeqCst);
+            fm.sync_dir()?;
+        }
+        for file_name in fm.file_names()? {
+            if is_temp_file(&file_name) {
+                fm.remove(&file_name)?;
+            }
+        }
+        Ok(fm)
+    }
+
+    pub fn read(&self, blk: &mut BlockId, page: &mut Page) -> DbResult<()> {
+        self.read_with_lsn(blk, page)?;
+        Ok(())
+    }
+
+    // Also returns the LSN recorded in the block header, or -1 without checksums.
+    pub fn read_with_lsn(&self, blk: &mut BlockId, page: &mut Page) -> DbResult<i64> {
+        let file = self.open_file(blk.file_name())?;
+        let size = self.physical_size() as usize;
+        let mut buf = AlignedBuf::new(size);
+        // read exactly one block; the part past the end of the file stays zeroed
+        let offset = blk.blk_num() * self.physical_size();
+        let mut read = 0;
+        while read < size {
+            match file.read_at(&mut buf.as_mut_slice()[read..], offset + read as u64) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let (header, contents) = buf.as_slice().split_at(self.header_size());
+        let mut lsn = -1;
+        // a block past the end of the file, or in a hole before a block
+        // written further on, was never written and has no header
+        if self.checksums && buf.as_slice().iter().any(|b| *b != 0) {
+            let stored = u32::from_le_bytes(header[0..4].try_into().unwrap());
+            if stored != crc32c(&buf.as_slice()[4..]) {
+                return Err(DbError::Corruption(format!(
+                    "checksum mismatch in block {} of {}",
+                    blk.blk_num(),
+                    blk.file_name()
+                )));
+            }
+            lsn = i64::from_le_bytes(header[8..16].try_into().unwrap());
+        }
+        page.set_buffer(ByteBuffer::from_bytes(contents));
+        Ok(lsn)
+    }
+
+    pub fn write(&self, blk: &mut BlockId, page: &mut Page) -> DbResult<()> {
+        self.write_with_lsn(blk, page, -1)
     }
 
-    pub fn append(&mut self, file_name: &mut String) -> BlockId {
-        let blk_num = self.length(file_name.to_string());
+    // Records `lsn` in the block header when there is one.
+    pub fn write_with_lsn(&self, blk: &mut BlockId, page: &mut Page, lsn: i64) -> DbResult<()> {
+        let mut buf = AlignedBuf::new(self.physical_size() as usize);
+        let contents = page.contents().as_bytes();
+        let len = contents.len().min(self.block_size as usize);
+        let header_size = self.header_size();
+        buf.as_mut_slice()[header_size..header_size + len].copy_from_slice(&contents[..len]);
+        self.write_block(blk, &mut buf, lsn)
+    }
+
+    pub fn append(&self, file_name: &mut String) -> DbResult<BlockId> {
+        let blk_num = self.length(file_name.to_string())?;
         let mut blk = BlockId::new(file_name.to_string(), blk_num);
-        let mut buffer = ByteBuffer::new();
-        buffer.resize(self.block_size.try_into().unwrap());
-        let file_path = Path::new(&self.db_dir).join(blk.file_name());
-        let mut file = match Self::is_new(file_path.to_str().unwrap()) {
-            true => OpenOptions::new()
+        let mut buf = AlignedBuf::new(self.physical_size() as usize);
+        self.write_block(&mut blk, &mut buf, -1)?;
+        Ok(blk)
+    }
+
+    pub fn length(&self, file_name: String) -> DbResult<u64> {
+        let file = self.open_file(&file_name)?;
+        Ok(file.metadata()?.len() / self.physical_size())
+    }
+
+    pub fn exists(&self, file_name: &str) -> bool {
+        Path::new(&self.db_dir).join(file_name).exists()
+    }
+
+    pub fn remove(&self, file_name: &str) -> DbResult<()> {
+        self.close_file(file_name);
+        fs::remove_file(Path::new(&self.db_dir).join(file_name))?;
+        self.unsynced.lock().unwrap().remove(file_name);
+        self.dir_changed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // Moves a file within the database directory, creating missing parent directories.
+    pub fn rename(&self, from: &str, to: &str) -> DbResult<()> {
+        let to_path = Path::new(&self.db_dir).join(to);
+        if let Some(parent) = to_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.close_file(from);
+        fs::rename(Path::new(&self.db_dir).join(from), to_path)?;
+        // the data moved with the file, so it is synced under the new name
+        let mut unsynced = self.unsynced.lock().unwrap();
+        if unsynced.remove(from) {
+            unsynced.insert(to.to_string());
+        }
+        self.dir_changed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // The names of the files directly inside the database directory, except
+    // for the header.
+    pub fn file_names(&self) -> DbResult<Vec<String>> {
+        Ok(fs::read_dir(&self.db_dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name != HEADER_FILE)
+            .collect())
+    }
+
+    // Whether the database directory was created by this file manager.
+    pub fn is_new(&self) -> bool {
+        self.is_new
+    }
+
+    // Creates an empty temporary file and returns its name.
+    pub fn create_temp_file(&self) -> DbResult<String> {
+        loop {
+            let n = self.next_temp.fetch_add(1, Ordering::SeqCst) + 1;
+            let file_name = format!("{TEMP_PREFIX}{n}");
+            let created = OpenOptions::new()
                 .write(true)
-                .read(true)
                 .create_new(true)
-                .open(file_path)
-                .unwrap(),
-            false => OpenOptions::new().write(true).open(file_path).unwrap(),
-        };
-        file.seek(SeekFrom::Start(blk.blk_num() * self.block_size))
-            .unwrap();
-        file.write(buffer.as_bytes()).unwrap();
-        blk
+                .open(Path::new(&self.db_dir).join(&file_name));
+            match created {
+                Ok(_) => {
+                    self.dir_changed.store(true, Ordering::SeqCst);
+                    return Ok(file_name);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    pub fn has_checksums(&self) -> bool {
+        self.checksums
+    }
+
+    pub fn header(&self) -> &DbHeader {
+        &self.header
     }
 
-    pub fn length(&mut self, file_name: String) -> u64 {
+    // Reads every block of every file in the database directory and returns
+    // the ones that fail their checksum.
+    pub fn verify(&self) -> DbResult<Vec<BlockId>> {
+        let mut corrupted = vec![];
+        let mut page = Page::new(self.block_size);
+        let mut file_names = self.file_names()?;
+        file_names.sort();
+        for file_name in file_names {
+            for blk_num in 0..self.length(file_name.clone())? {
+                let mut blk = BlockId::new(file_name.clone(), blk_num);
+                match self.read(&mut blk, &mut page) {
+                    Ok(()) => {}
+                    Err(DbError::Corruption(_)) => corrupted.push(blk),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(corrupted)
+    }
+
+    // The policy of files that have none of their own; OnCommit by default.
+    pub fn set_default_sync_policy(&self, policy: SyncPolicy) {
+        *self.default_sync_policy.lock().unwrap() = policy;
+    }
+
+    // Applies to the file and to the files named after it, such as the
+    // segments `ruspledb.log.000001`, ... of the log file `ruspledb.log`.
+    pub fn set_sync_policy(&self, file_name: &str, policy: SyncPolicy) {
+        self.sync_policies
+            .lock()
+            .unwrap()
+            .insert(file_name.to_string(), policy);
+    }
+
+    pub fn sync_policy(&self, file_name: &str) -> SyncPolicy {
+        let policies = self.sync_policies.lock().unwrap();
+        let mut name = file_name;
+        loop {
+            if let Some(policy) = policies.get(name) {
+                return *policy;
+            }
+            match name.rfind('.') {
+                Some(pos) => name = &name[..pos],
+                None => return *self.default_sync_policy.lock().unwrap(),
+            }
+        }
+    }
+
+    // Forces the files written under OnCommit since their last sync, and the
+    // directory entries of files created, renamed or removed since then.
+    pub fn sync(&self) -> DbResult<()> {
+        let unsynced: Vec<String> = self.unsynced.lock().unwrap().drain().collect();
+        for file_name in unsynced {
+            self.open_file(&file_name)?.sync_data()?;
+        }
+        self.sync_dir()
+    }
+
+    // Bypasses the operating system's cache. Blocks must then be a multiple
+    // of DIRECT_IO_ALIGN bytes, and the file system has to support O_DIRECT.
+    pub fn set_direct_io(&self, direct_io: bool) -> DbResult<()> {
+        if direct_io && !(self.physical_size() as usize).is_multiple_of(DIRECT_IO_ALIGN) {
+            return Err(DbError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "block size {} with header is not a multiple of {DIRECT_IO_ALIGN}",
+                    self.physical_size()
+                ),
+            )));
+        }
+        // the open files are reopened with the new flags
+        let mut handles = self.handles.lock().unwrap();
+        handles.files.clear();
+        self.direct_io.store(direct_io, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn is_direct_io(&self) -> bool {
+        self.direct_io.load(Ordering::SeqCst)
+    }
+
+    // Closes the file once the operations already using it are done; it is
+    // reopened the next time it is needed.
+    pub fn close_file(&self, file_name: &str) {
+        self.handles.lock().unwrap().files.remove(file_name);
+    }
+
+    // At least 1; closes the least recently used files that no longer fit.
+    pub fn set_max_open_files(&self, capacity: usize) {
+        let mut handles = self.handles.lock().unwrap();
+        handles.capacity = capacity.max(1);
+        while handles.files.len() > handles.capacity {
+            handles.evict();
+        }
+    }
+
+    pub fn open_files(&self) -> usize {
+        self.handles.lock().unwrap().files.len()
+    }
+
+    // The size of a block on disk.
+    fn physical_size(&self) -> u64 {
+        self.block_size + self.header_size() as u64
+    }
+
+    fn header_size(&self) -> usize {
+        if self.checksums {
+            PAGE_HEADER_SIZE as usize
+        } else {
+            0
+        }
+    }
+
+    fn write_block(&self, blk: &mut BlockId, buf: &mut AlignedBuf, lsn: i64) -> DbResult<()> {
+        if self.checksums {
+            let block = buf.as_mut_slice();
+            block[8..16].copy_from_slice(&lsn.to_le_bytes());
+            let crc = crc32c(&block[4..]);
+            block[0..4].copy_from_slice(&crc.to_le_bytes());
+        }
+        let file = self.open_file(blk.file_name())?;
+        file.write_all_at(buf.as_slice(), blk.blk_num() * self.physical_size())?;
+        match self.sync_policy(blk.file_name()) {
+            SyncPolicy::Always => {
+                file.sync_data()?;
+                self.sync_dir()?;
+            }
+            SyncPolicy::OnCommit => {
+                self.unsynced
+                    .lock()
+                    .unwrap()
+                    .insert(blk.file_name().to_string());
+            }
+            SyncPolicy::Never => {}
+        }
+        Ok(())
+    }
+
+    fn sync_dir(&self) -> DbResult<()> {
+        if self.dir_changed.swap(false, Ordering::SeqCst) {
+            File::open(&self.db_dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn open_file(&self, file_name: &str) -> DbResult<Arc<File>> {
+        let mut handles = self.handles.lock().unwrap();
+        handles.clock += 1;
+        let now = handles.clock;
+        if let Some((file, last_used)) = handles.files.get_mut(file_name) {
+            *last_used = now;
+            return Ok(file.clone());
+        }
         let file_path = Path::new(&self.db_dir).join(file_name);
-        let file = match Self::is_new(file_path.to_str().unwrap()) {
-            true => OpenOptions::new()
-                .write(true)
-                .read(true)
-                .create_new(true)
-                .open(file_path)
-                .unwrap(),
-            false => OpenOptions::new().read(true).open(file_path).unwrap(),
-        };
-        file.metadata().unwrap().len() / self.block_size
+        if !file_path.exists() {
+            self.dir_changed.store(true, Ordering::SeqCst);
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).read(true).create(true).truncate(false);
+        if self.is_direct_io() {
+            options.custom_flags(libc::O_DIRECT);
+        }
+        let file = Arc::new(options.open(file_path)?);
+        if handles.files.len() >= handles.capacity {
+            handles.evict();
+        }
+        handles
+            .files
+            .insert(file_name.to_string(), (file.clone(), now));
+        Ok(file)
+    }
+}
+
+impl HandleCache {
+    fn evict(&mut self) {
+        let oldest = self
+            .files
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(name, _)| name.clone());
+        if let Some(name) = oldest {
+            self.files.remove(&name);
+        }
     }
+}
 
-    pub fn is_new(path: &str) -> bool {
-        !Path::new(path).exists()
+// A zeroed block whose start is aligned for O_DIRECT, carved out of a
+// slightly larger allocation.
+struct AlignedBuf {
+    buf: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> Self {
+        let buf = vec![0; len + DIRECT_IO_ALIGN];
+        let start = buf.as_ptr().align_offset(DIRECT_IO_ALIGN);
+        AlignedBuf { buf, start, len }
     }
 
-    pub fn block_size(&mut self) -> u64 {
-        self.block_size
+    fn as_slice(&self) -> &[u8] {
+        &self.buf[self.start..self.start + self.len]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.buf[self.start..self.start + self.len]
+    }
+}
+
+// Only the names create_temp_file hands out, so that a table such as
+// "temperature" is left alone.
+fn is_temp_file(file_name: &str) -> bool {
+    file_name
+        .strip_prefix(TEMP_PREFIX)
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+// Leftover temporaries don't make a directory a database.
+fn has_data_files(db_dir: &str) -> DbResult<bool> {
+    for entry in fs::read_dir(db_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.path().is_file() && !is_temp_file(&name.to_string_lossy()) {
+            return Ok(true);
+        }
     }
+    Ok(false)
 }
@@ -1,5 +1,30 @@
 use bytebuffer::ByteBuffer;
-use std::ops::Add;
+
+use crate::error::{DbError, DbResult};
+
+// Every value is stored big-endian at the offset it is given, in a fixed
+// number of bytes: 2 for an i16, 4 for an i32 or a date, 8 for an i64, u64,
+// f64 or timestamp, and 1 for a bool. Strings and blobs are a 4-byte length
+// followed by that many bytes, UTF-8 for strings; a fixed string always takes
+// its full width, padded with zero bytes. A value that doesn't fit in a
+// block's page is refused.
+pub const I16_SIZE: usize = 2;
+pub const I32_SIZE: usize = 4;
+pub const I64_SIZE: usize = 8;
+pub const F64_SIZE: usize = 8;
+pub const BOOL_SIZE: usize = 1;
+pub const DATE_SIZE: usize = 4;
+pub const TIMESTAMP_SIZE: usize = 8;
+// the length before a string or a blob
+pub const LENGTH_SIZE: usize = 4;
+
+// Days since 1970-01-01.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Date(pub i32);
+
+// Microseconds since 1970-01-01 00:00:00 UTC.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Timestamp(pub i64);
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Page {
@@ -25,96 +50,164 @@ impl Page {
         self.buffer = buf
     }
 
-    pub fn get_bytes(&mut self, offset: usize) -> Vec<u8> {
-        self.buffer.set_rpos(offset);
-        let length = self.buffer.read_u64().unwrap();
-        self.buffer.set_rpos(offset);
-        self.buffer.read_bytes(length.try_into().unwrap()).unwrap()
+    // The bytes a string or blob of `len` bytes takes up.
+    pub fn max_length(len: usize) -> usize {
+        LENGTH_SIZE + len
     }
 
-    pub fn get_log_bytes(&mut self, offset: usize) -> Vec<u8> {
-        self.buffer.set_rpos(offset);
-        // read string length
-        let length = self.buffer.read_u32().unwrap();
-        // re set offset
-        self.buffer.set_rpos(offset);
-        /*
-          record10 , 110
-          [0, 0, 0, 8, 114, 101, 99, 111, 114, 100, 49, 48, 0, 0, 0, 110]
-          record9 , 109
-          [0, 0, 0, 7, 114, 101, 99, 111, 114, 100, 57, 0, 0, 0, 109]
-        */
-        self.buffer
-            .read_bytes(length.add(8).try_into().unwrap())
-            .unwrap()
+    pub fn get_i16(&self, offset: usize) -> DbResult<i16> {
+        Ok(i16::from_be_bytes(self.read_array(offset)?))
     }
 
-    pub fn get_u64(&mut self, offset: usize) -> u64 {
-        self.buffer.set_rpos(offset);
-        self.buffer.read_u64().unwrap()
+    pub fn get_i32(&self, offset: usize) -> DbResult<i32> {
+        Ok(i32::from_be_bytes(self.read_array(offset)?))
     }
 
-    pub fn get_u32(&mut self, offset: usize) -> u32 {
-        self.buffer.set_rpos(offset);
-        self.buffer.read_u32().unwrap()
+    pub fn get_i64(&self, offset: usize) -> DbResult<i64> {
+        Ok(i64::from_be_bytes(self.read_array(offset)?))
     }
 
-    pub fn get_i32(&mut self, offset: usize) -> i32 {
-        self.buffer.set_rpos(offset);
-        self.buffer.read_i32().unwrap()
+    // Block numbers, log boundaries and other unsigned bookkeeping.
+    pub fn get_u64(&self, offset: usize) -> DbResult<u64> {
+        Ok(u64::from_be_bytes(self.read_array(offset)?))
     }
 
-    pub fn get_string(&mut self, offset: usize) -> String {
-        self.buffer.set_rpos(offset);
-        self.buffer.read_string().unwrap()
+    pub fn get_f64(&self, offset: usize) -> DbResult<f64> {
+        Ok(f64::from_be_bytes(self.read_array(offset)?))
     }
 
-    pub fn set_bytes(&mut self, offset: usize, buffer: Vec<u8>) {
-        if self.buffer.len() < offset {
-            self.buffer.resize(offset + buffer.len())
+    pub fn get_bool(&self, offset: usize) -> DbResult<bool> {
+        match self.read_array::<BOOL_SIZE>(offset)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [b] => Err(DbError::Corruption(format!("offset {offset}: bool {b}"))),
         }
-        self.buffer.set_wpos(offset);
-        self.buffer.write_bytes(&buffer);
     }
 
-    pub fn set_u64(&mut self, offset: usize, n: u64) {
-        if self.buffer.len() < offset {
-            self.buffer.resize(offset + n.to_be_bytes().len())
-        }
-        self.buffer.set_wpos(offset);
-        self.buffer.write_u64(n);
+    pub fn get_date(&self, offset: usize) -> DbResult<Date> {
+        Ok(Date(self.get_i32(offset)?))
     }
 
-    pub fn set_u32(&mut self, offset: usize, n: u32) {
-        if self.buffer.len() < offset {
-            self.buffer.resize(offset + n.to_be_bytes().len())
-        }
-        self.buffer.set_wpos(offset);
-        self.buffer.write_u32(n);
+    pub fn get_timestamp(&self, offset: usize) -> DbResult<Timestamp> {
+        Ok(Timestamp(self.get_i64(offset)?))
     }
 
-    pub fn set_i32(&mut self, offset: usize, n: i32) {
-        if self.buffer.len() < offset {
-            self.buffer.resize(offset + n.to_be_bytes().len())
-        }
-        self.buffer.set_wpos(offset);
-        self.buffer.write_i32(n);
+    pub fn get_bytes(&self, offset: usize) -> DbResult<Vec<u8>> {
+        let len = u32::from_be_bytes(self.read_array(offset)?) as usize;
+        Ok(self.read(offset + LENGTH_SIZE, len)?.to_vec())
     }
 
-    pub fn set_string(&mut self, offset: usize, s: String) {
-        if self.buffer.len() < offset {
-            self.buffer.resize(offset + s.len())
-        }
-        self.buffer.set_wpos(offset);
-        self.buffer.write_string(&s);
+    pub fn get_string(&self, offset: usize) -> DbResult<String> {
+        String::from_utf8(self.get_bytes(offset)?)
+            .map_err(|err| DbError::Corruption(format!("offset {offset}: {err}")))
+    }
+
+    // A string written by set_fixed_string with the same width.
+    pub fn get_fixed_string(&self, offset: usize, width: usize) -> DbResult<String> {
+        let bytes = self.read(offset, width)?;
+        let len = bytes.iter().position(|b| *b == 0).unwrap_or(width);
+        String::from_utf8(bytes[..len].to_vec())
+            .map_err(|err| DbError::Corruption(format!("offset {offset}: {err}")))
+    }
+
+    pub fn set_i16(&mut self, offset: usize, n: i16) -> DbResult<()> {
+        self.write(offset, &n.to_be_bytes())
+    }
+
+    pub fn set_i32(&mut self, offset: usize, n: i32) -> DbResult<()> {
+        self.write(offset, &n.to_be_bytes())
+    }
+
+    pub fn set_i64(&mut self, offset: usize, n: i64) -> DbResult<()> {
+        self.write(offset, &n.to_be_bytes())
+    }
+
+    pub fn set_u64(&mut self, offset: usize, n: u64) -> DbResult<()> {
+        self.write(offset, &n.to_be_bytes())
+    }
+
+    pub fn set_f64(&mut self, offset: usize, n: f64) -> DbResult<()> {
+        self.write(offset, &n.to_be_bytes())
+    }
+
+    pub fn set_bool(&mut self, offset: usize, b: bool) -> DbResult<()> {
+        self.write(offset, &[b as u8])
     }
 
-    pub fn max_length(&mut self, str_len: usize) -> usize {
-        4 + str_len
+    pub fn set_date(&mut self, offset: usize, date: Date) -> DbResult<()> {
+        self.set_i32(offset, date.0)
+    }
+
+    pub fn set_timestamp(&mut self, offset: usize, ts: Timestamp) -> DbResult<()> {
+        self.set_i64(offset, ts.0)
+    }
+
+    pub fn set_bytes(&mut self, offset: usize, bytes: Vec<u8>) -> DbResult<()> {
+        let len: u32 = bytes.len().try_into().unwrap();
+        let mut field = len.to_be_bytes().to_vec();
+        field.extend(bytes);
+        self.write(offset, &field)
+    }
+
+    pub fn set_string(&mut self, offset: usize, s: String) -> DbResult<()> {
+        self.set_bytes(offset, s.into_bytes())
+    }
+
+    // Takes exactly `width` bytes; a string with a NUL byte doesn't read back whole.
+    pub fn set_fixed_string(&mut self, offset: usize, s: &str, width: usize) -> DbResult<()> {
+        if s.len() > width {
+            return Err(DbError::SchemaMismatch(format!(
+                "string of {} bytes in a field of {width}",
+                s.len()
+            )));
+        }
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize(width, 0);
+        self.write(offset, &bytes)
+    }
+
+    // Whether `len` bytes can be written at offset.
+    pub fn check_fits(&self, offset: usize, len: usize) -> DbResult<()> {
+        if self.block_size > 0 && offset + len > self.buffer.len() {
+            return Err(DbError::Overflow(format!(
+                "offset {offset}: {len} bytes in a block of {}",
+                self.block_size
+            )));
+        }
+        Ok(())
     }
 
     pub fn contents(&mut self) -> &ByteBuffer {
         self.buffer.set_rpos(0);
         &self.buffer
     }
+
+    // A read that runs past the page means the bytes at offset aren't what
+    // was written there.
+    fn read(&self, offset: usize, len: usize) -> DbResult<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.buffer.as_bytes().get(offset..end))
+            .ok_or_else(|| {
+                DbError::Corruption(format!("offset {offset}: {len} bytes past the page"))
+            })
+    }
+
+    fn read_array<const N: usize>(&self, offset: usize) -> DbResult<[u8; N]> {
+        Ok(self.read(offset, N)?.try_into().unwrap())
+    }
+
+    // A block's page keeps its size, since only block_size bytes of it reach
+    // the disk. A page without a block, e.g. one a log record is encoded in,
+    // grows if need be.
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> DbResult<()> {
+        self.check_fits(offset, bytes.len())?;
+        let end = offset + bytes.len();
+        if self.buffer.len() < end {
+            self.buffer.resize(end);
+        }
+        self.buffer.set_wpos(offset);
+        self.buffer.write_bytes(bytes);
+        Ok(())
+    }
 }
@@ -1,5 +1,7 @@
 pub mod buffer;
+pub mod error;
 pub mod file;
 pub mod logging;
 pub mod record;
+pub mod server;
 pub mod tx;
@@ -1,9 +1,19 @@
 use bytebuffer::ByteBuffer;
+use std::sync::Arc;
 
-use crate::file::{block_id::BlockId, file_mgr::FileMgr, page::Page};
+use crate::{
+    error::DbResult,
+    file::{block_id::BlockId, file_mgr::FileMgr, page::Page},
+};
 
+use super::log_mgr::LogMgr;
+
+// Walks the log backward, moving on to the previous segment
+// once the first block of a segment is done.
 pub struct LogIterator {
-    fm: FileMgr,
+    fm: Arc<FileMgr>,
+    log_file: String,
+    segment: u64,
     blk: BlockId,
     page: Page,
     current_pos: usize,
@@ -11,43 +21,91 @@ pub struct LogIterator {
 }
 
 impl LogIterator {
-    pub fn new(fm: &mut FileMgr, blk: &mut BlockId) -> Self {
+    pub fn new(
+        fm: Arc<FileMgr>,
+        log_file: String,
+        segment: u64,
+        blk: &mut BlockId,
+    ) -> DbResult<Self> {
         let mut buffer = ByteBuffer::new();
         buffer.resize(fm.block_size().try_into().unwrap());
         let mut page = Page::new_from_buffer(&mut buffer);
 
-        fm.read(blk, &mut page);
-        let boundary = page.get_u64(0);
+        fm.read(blk, &mut page)?;
+        let boundary = page.get_u64(0)?;
 
-        LogIterator {
-            fm: fm.to_owned(),
+        Ok(LogIterator {
+            fm,
+            log_file,
+            segment,
             blk: blk.to_owned(),
             page,
             current_pos: boundary.try_into().unwrap(),
             boundary: boundary.try_into().unwrap(),
+        })
+    }
+
+    pub fn has_next(&mut self) -> DbResult<bool> {
+        Ok(self.current_pos < self.fm.block_size().try_into().unwrap()
+            || self.blk.blk_num() > 0
+            || self.previous_segment()?.is_some())
+    }
+
+    // The segment of the record returned last.
+    pub fn segment(&self) -> u64 {
+        self.segment
+    }
+
+    fn previous_segment(&self) -> DbResult<Option<String>> {
+        if self.segment <= 1 {
+            return Ok(None);
+        }
+        let name = LogMgr::segment_name(&self.log_file, self.segment - 1);
+        if self.fm.exists(&name) && self.fm.length(name.clone())? > 0 {
+            Ok(Some(name))
+        } else {
+            Ok(None)
         }
     }
 
-    pub fn has_next(&mut self) -> bool {
-        self.current_pos < self.fm.block_size().try_into().unwrap() || self.blk.blk_num() > 0
+    fn move_to_block(&mut self, mut blk: BlockId) -> DbResult<()> {
+        self.fm.read(&mut blk, &mut self.page)?;
+        self.blk = blk;
+        self.boundary = self.page.get_u64(0)?.try_into().unwrap();
+        self.current_pos = self.boundary;
+        Ok(())
     }
 
-    pub fn next(&mut self) -> Vec<u8> {
-        if self.current_pos == self.fm.block_size().try_into().unwrap() {
-            // blk_num -= 1
-            self.blk = BlockId::new(self.blk.file_name().to_string(), self.blk.blk_num() - 1);
-            self.fm.read(&mut self.blk, &mut self.page);
-            self.boundary = self.page.get_u64(0).try_into().unwrap();
-            self.current_pos = self.boundary;
+    fn read_next(&mut self) -> DbResult<Option<Vec<u8>>> {
+        while self.current_pos == self.fm.block_size() as usize {
+            let blk = if self.blk.blk_num() > 0 {
+                // blk_num -= 1
+                BlockId::new(self.blk.file_name().to_string(), self.blk.blk_num() - 1)
+            } else {
+                // last block of the previous segment
+                let Some(name) = self.previous_segment()? else {
+                    return Ok(None);
+                };
+                self.segment -= 1;
+                let last = self.fm.length(name.clone())? - 1;
+                BlockId::new(name, last)
+            };
+            self.move_to_block(blk)?;
         }
-        let rec = self.page.get_log_bytes(self.current_pos);
+        let rec = self.page.get_bytes(self.current_pos)?;
         self.current_pos += 4 + rec.len();
-        rec
+        Ok(Some(rec))
     }
+}
 
-    // fn move_to_block(&mut self, mut blk: BlockId) {
-    //     self.fm.read(&mut blk, &mut self.page);
-    //     self.boundary = self.page.get_u64(0).try_into().unwrap();
-    //     self.current_pos = self.boundary;
-    // }
+impl Iterator for LogIterator {
+    type Item = DbResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<DbResult<Vec<u8>>> {
+        match self.has_next() {
+            Ok(true) => self.read_next().transpose(),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
 }
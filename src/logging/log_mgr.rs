@@ -1,99 +1,235 @@
-use crate::file::{block_id::BlockId, file_mgr::FileMgr, page::Page};
+use crate::{
+    error::DbResult,
+    file::{block_id::BlockId, file_mgr::FileMgr, page::Page},
+};
 use bytebuffer::ByteBuffer;
+use std::{collections::HashSet, sync::Arc};
 
 use super::log_iterator::LogIterator;
 
-#[derive(Clone, PartialEq, Debug)]
+pub const ARCHIVE_DIR: &str = "log_archive";
+// A log block starts with the boundary, the offset of its newest record, and
+// the LSN of that record, so that numbering carries on when the log is opened
+// again. The records fill the block from the end.
+const LSN_POS: usize = 8;
+const LOG_HEADER_SIZE: usize = 16;
+
+// What happens to the log segments a checkpoint has made obsolete.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LogRetention {
+    Keep,
+    Truncate,
+    Archive,
+}
+
+// The log is a sequence of numbered segment files, e.g. `ruspledb.log.000001`.
+// Records are always appended to the newest one.
+#[derive(Clone, Debug)]
 pub struct LogMgr {
-    fm: FileMgr,
+    fm: Arc<FileMgr>,
     log_file: String,
+    segment: u64,
     log_page: Page,
     current_blk: BlockId,
     latest_lsn: i64,
+    // written to the file, but maybe only as far as the operating system
     last_saved_lsn: i64,
+    // forced to stable storage
+    last_synced_lsn: i64,
+    // blocks with a full-page image in the log since the latest checkpoint
+    imaged_blocks: HashSet<BlockId>,
 }
 
 impl LogMgr {
-    pub fn new(mut fm: &mut FileMgr, log_file: &mut String) -> Self {
+    pub fn new(fm: Arc<FileMgr>, log_file: &mut String) -> DbResult<Self> {
         let mut buffer = ByteBuffer::new();
         buffer.resize(fm.block_size().try_into().unwrap());
         let mut log_page = Page::new_from_buffer(&mut buffer);
-        let log_size = fm.length(log_file.to_string());
+        let segments = Self::segments(&fm, log_file)?;
+        let segment = match segments.last() {
+            Some(segment) => *segment,
+            None => {
+                // a log from before segments becomes the first segment
+                if fm.exists(log_file) {
+                    fm.rename(log_file, &Self::segment_name(log_file, 1))?;
+                }
+                1
+            }
+        };
+        let mut segment_file = Self::segment_name(log_file, segment);
+        let log_size = fm.length(segment_file.clone())?;
         let mut current_blk = match log_size {
-            0 => Self::append_new_block(&mut fm, log_file, &mut log_page),
-            _ => BlockId::new(log_file.to_string(), log_size - 1),
+            0 => Self::append_new_block(&fm, &mut segment_file, &mut log_page, 0)?,
+            _ => BlockId::new(segment_file, log_size - 1),
         };
         if log_size != 0 {
-            fm.read(&mut current_blk, &mut log_page)
+            fm.read(&mut current_blk, &mut log_page)?
         }
-        LogMgr {
-            fm: fm.to_owned(),
+        let latest_lsn = log_page.get_i64(LSN_POS)?;
+        Ok(LogMgr {
+            fm,
             log_file: log_file.to_string(),
+            segment,
             log_page,
             current_blk,
-            latest_lsn: 0,
-            last_saved_lsn: 0,
-        }
+            latest_lsn,
+            last_saved_lsn: latest_lsn,
+            last_synced_lsn: latest_lsn,
+            imaged_blocks: HashSet::new(),
+        })
+    }
+
+    pub fn iterator(&mut self) -> DbResult<LogIterator> {
+        self.flush()?;
+        LogIterator::new(
+            self.fm.clone(),
+            self.log_file.clone(),
+            self.segment,
+            &mut self.current_blk,
+        )
+    }
+
+    pub fn segment_name(log_file: &str, segment: u64) -> String {
+        format!("{log_file}.{segment:06}")
+    }
+
+    pub fn current_segment(&mut self) -> u64 {
+        self.segment
     }
 
-    pub fn iterator(&mut self) -> LogIterator {
-        self.flush();
-        LogIterator::new(&mut self.fm, &mut self.current_blk)
+    // Flushes the current segment and continues the log in a new one.
+    pub fn rotate(&mut self) -> DbResult<u64> {
+        self.flush()?;
+        self.segment += 1;
+        let mut segment_file = Self::segment_name(&self.log_file, self.segment);
+        self.current_blk = Self::append_new_block(
+            &self.fm,
+            &mut segment_file,
+            &mut self.log_page,
+            self.latest_lsn,
+        )?;
+        Ok(self.segment)
+    }
+
+    // Deletes or archives every segment older than `segment`.
+    pub fn discard_before(&mut self, segment: u64, retention: LogRetention) -> DbResult<()> {
+        if retention == LogRetention::Keep {
+            return Ok(());
+        }
+        for old in Self::segments(&self.fm, &self.log_file)? {
+            if old >= segment.min(self.segment) {
+                break;
+            }
+            let name = Self::segment_name(&self.log_file, old);
+            match retention {
+                LogRetention::Archive => self.fm.rename(&name, &format!("{ARCHIVE_DIR}/{name}"))?,
+                _ => self.fm.remove(&name)?,
+            }
+        }
+        Ok(())
     }
 
-    pub fn append(&mut self, log_rec: Vec<u8>) -> i64 {
-        let mut boundary = self.log_page.get_u64(0);
+    pub fn append(&mut self, log_rec: Vec<u8>) -> DbResult<i64> {
+        let mut boundary = self.log_page.get_u64(0)?;
         let rec_size = log_rec.len();
         let bytes_needed = rec_size + 4;
         let sub = boundary.checked_sub(bytes_needed.try_into().unwrap());
-        match sub {
-            Some(result) => {
-                if result < 4 {
-                    self.flush();
-                    self.current_blk = Self::append_new_block(
-                        &mut self.fm,
-                        &mut self.log_file,
-                        &mut self.log_page,
-                    );
-                    boundary = self.log_page.get_u64(0)
-                }
-            }
-            None => {
-                self.flush();
-                self.current_blk =
-                    Self::append_new_block(&mut self.fm, &mut self.log_file, &mut self.log_page);
-                boundary = self.log_page.get_u64(0)
-            }
+        let fits = match sub {
+            Some(result) => result >= LOG_HEADER_SIZE as u64,
+            None => false,
+        };
+        if !fits {
+            self.flush()?;
+            let mut segment_file = self.current_blk.file_name().to_string();
+            self.current_blk = Self::append_new_block(
+                &self.fm,
+                &mut segment_file,
+                &mut self.log_page,
+                self.latest_lsn,
+            )?;
+            boundary = self.log_page.get_u64(0)?
         }
         let rec_pos = boundary as usize - bytes_needed;
 
-        self.log_page.set_bytes(rec_pos, log_rec);
+        self.log_page.set_bytes(rec_pos, log_rec)?;
         // set the new boundary
-        self.log_page.set_u64(0, rec_pos.try_into().unwrap());
+        self.log_page.set_u64(0, rec_pos.try_into().unwrap())?;
         self.latest_lsn += 1;
-        self.latest_lsn
+        self.log_page.set_i64(LSN_POS, self.latest_lsn)?;
+        Ok(self.latest_lsn)
+    }
+
+    // The longest record that fits in a log block.
+    pub fn max_record_len(&self) -> usize {
+        // the block's header and the record's length prefix
+        self.fm.block_size() as usize - LOG_HEADER_SIZE - 4
     }
 
-    fn append_new_block(fm: &mut FileMgr, log_file: &mut String, log_page: &mut Page) -> BlockId {
-        let mut blk = fm.append(log_file);
-        // write block_size(400) to buffer
-        log_page.set_u64(0, fm.block_size());
-        fm.write(&mut blk, log_page);
-        blk
+    // Whether the next change to the block must be preceded by an image of
+    // the whole page; true once per block after each checkpoint. Only blocks
+    // with checksums can be found torn, so only they are imaged.
+    pub fn needs_page_image(&mut self, blk: &BlockId) -> bool {
+        self.fm.has_checksums() && self.imaged_blocks.insert(blk.clone())
+    }
+
+    // Called when a checkpoint record is written.
+    pub fn reset_page_images(&mut self) {
+        self.imaged_blocks.clear();
+    }
+
+    fn append_new_block(
+        fm: &FileMgr,
+        log_file: &mut String,
+        log_page: &mut Page,
+        latest_lsn: i64,
+    ) -> DbResult<BlockId> {
+        let mut blk = fm.append(log_file)?;
+        // no records yet, so the boundary is the end of the block
+        log_page.set_u64(0, fm.block_size())?;
+        log_page.set_i64(LSN_POS, latest_lsn)?;
+        fm.write(&mut blk, log_page)?;
+        Ok(blk)
     }
 
     pub fn get_last_saved_lsn(&mut self) -> i64 {
         self.last_saved_lsn
     }
 
-    pub fn flush_with_lsn(&mut self, lsn: i64) {
-        if lsn >= self.last_saved_lsn {
-            self.flush()
+    pub fn get_last_synced_lsn(&mut self) -> i64 {
+        self.last_synced_lsn
+    }
+
+    // Forcing the log is what makes a commit durable, so the files written
+    // under SyncPolicy::OnCommit are synced along with it. A record already
+    // written by a full page, a rotation or an iterator still has to be synced.
+    pub fn flush_with_lsn(&mut self, lsn: i64) -> DbResult<()> {
+        if lsn > self.last_synced_lsn {
+            if lsn >= self.last_saved_lsn {
+                self.flush()?;
+            }
+            self.fm.sync()?;
+            self.last_synced_lsn = self.last_saved_lsn;
         }
+        Ok(())
+    }
+
+    // The numbers of the existing segments, oldest first.
+    fn segments(fm: &FileMgr, log_file: &str) -> DbResult<Vec<u64>> {
+        let prefix = format!("{log_file}.");
+        let mut segments: Vec<u64> = fm
+            .file_names()?
+            .iter()
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .filter_map(|num| num.parse().ok())
+            .collect();
+        segments.sort();
+        Ok(segments)
     }
 
-    fn flush(&mut self) {
-        self.fm.write(&mut self.current_blk, &mut self.log_page);
+    fn flush(&mut self) -> DbResult<()> {
+        self.fm
+            .write_with_lsn(&mut self.current_blk, &mut self.log_page, self.latest_lsn)?;
         self.last_saved_lsn = self.latest_lsn;
+        Ok(())
     }
 }
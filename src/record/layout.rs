@@ -1,5 +1,10 @@
 use std::collections::HashMap;
 
+use crate::{
+    error::{DbError, DbResult},
+    file::page::{Page, I32_SIZE},
+};
+
 use super::schema::Schema;
 
 #[derive(Clone)]
@@ -10,32 +15,39 @@ pub struct Layout {
 }
 
 impl Layout {
-    pub fn new(schema: &mut Schema) -> Self {
+    pub fn new(schema: &mut Schema) -> DbResult<Self> {
         let mut offsets: HashMap<String, u64> = HashMap::new();
-        let mut pos = 4; // leave space for the empty/inuse flag
+        let mut pos = I32_SIZE as u64; // leave space for the empty/inuse flag
         for field_name in schema.fields().iter_mut() {
             offsets.insert(field_name.to_string(), pos);
-            let field_type = schema.get_type(&field_name);
+            let field_type = schema.get_type(field_name)?;
             pos += match field_type {
-                // Integer.BYTES
-                4 => 4 + 4, // also record_page#get_int_u32 plus 4
-                // max_length
-                _ => 4 + schema.length(&field_name),
-            };
+                // INTEGER
+                4 => I32_SIZE,
+                _ => Page::max_length(schema.length(field_name)? as usize),
+            } as u64;
         }
-        Layout {
+        Ok(Layout {
             schema: schema.to_owned(),
             offsets,
-            slot_size: pos.try_into().unwrap(),
-        }
+            slot_size: pos,
+        })
     }
 
     pub fn schema(&mut self) -> Schema {
         self.schema.to_owned()
     }
 
-    pub fn offset(&mut self, field_name: &str) -> u64 {
-        self.offsets.get(field_name).unwrap().to_owned()
+    pub fn offset(&mut self, field_name: &str) -> DbResult<u64> {
+        self.offsets
+            .get(field_name)
+            .copied()
+            .ok_or_else(|| DbError::SchemaMismatch(format!("no field {field_name}")))
+    }
+
+    // The most bytes a string field can hold.
+    pub fn length(&mut self, field_name: &str) -> DbResult<u64> {
+        self.schema.length(field_name)
     }
 
     pub fn slot_size(&mut self) -> u64 {
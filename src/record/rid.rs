@@ -8,4 +8,12 @@ impl RID {
     pub fn new(blk_num: u64, slot: u64) -> Self {
         RID { blk_num, slot }
     }
+
+    pub fn blk_num(&self) -> u64 {
+        self.blk_num
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.slot
+    }
 }
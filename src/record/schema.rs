@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
-#[derive(Clone)]
+use crate::error::{DbError, DbResult};
+
+#[derive(Clone, Default)]
 pub struct Schema {
     fields: Vec<String>,
     info: HashMap<String, FiledInfo>,
@@ -40,11 +42,17 @@ impl Schema {
         self.fields.to_owned()
     }
 
-    pub fn get_type(&mut self, field_name: &str) -> u64 {
-        self.info.get(field_name).unwrap().field_type
+    pub fn get_type(&mut self, field_name: &str) -> DbResult<u64> {
+        Ok(self.info(field_name)?.field_type)
     }
 
-    pub fn length(&mut self, field_name: &str) -> u64 {
-        self.info.get(field_name).unwrap().length
+    pub fn length(&mut self, field_name: &str) -> DbResult<u64> {
+        Ok(self.info(field_name)?.length)
+    }
+
+    fn info(&self, field_name: &str) -> DbResult<&FiledInfo> {
+        self.info
+            .get(field_name)
+            .ok_or_else(|| DbError::SchemaMismatch(format!("no field {field_name}")))
     }
 }
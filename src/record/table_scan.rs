@@ -1,4 +1,4 @@
-use crate::{file::block_id::BlockId, tx::transaction::Transaction};
+use crate::{error::DbResult, file::block_id::BlockId, tx::transaction::Transaction};
 
 use super::{layout::Layout, record_page::RecordPage, rid::RID};
 
@@ -12,71 +12,66 @@ pub struct TableScan {
 }
 
 impl TableScan {
-    pub fn new(tx: &mut Transaction, table_name: &str, layout: Layout) -> Self {
-        let mut rp: Option<RecordPage> = None;
-        let mut current_slot = 0;
+    pub fn new(tx: &mut Transaction, table_name: &str, layout: Layout) -> DbResult<Self> {
         let file_name = table_name.to_string() + ".tbl";
-        if tx.size(&file_name) == 0 {
+        let rp = if tx.size(&file_name)? == 0 {
             // move_to_new_block
-            let mut blk = tx.append(&file_name);
-            rp = Some(RecordPage::new(tx, &mut blk, layout.clone()));
-            rp.as_mut().unwrap().format();
-            current_slot -= 1;
+            let mut blk = tx.append(&file_name)?;
+            let mut rp = RecordPage::new(tx, &mut blk, layout.clone())?;
+            rp.format()?;
+            rp
         } else {
             // move_to_block(0)
             let mut blk = BlockId::new(file_name.to_string(), 0);
-            rp = Some(RecordPage::new(tx, &mut blk, layout.clone()));
-            current_slot -= 1;
+            RecordPage::new(tx, &mut blk, layout.clone())?
         };
-        TableScan {
+        let current_slot = -1;
+        Ok(TableScan {
             tx: tx.to_owned(),
             table_name: table_name.to_string(),
             layout,
-            rp,
+            rp: Some(rp),
             current_slot,
             file_name,
-        }
+        })
+    }
+
+    pub fn table_name(&mut self) -> &String {
+        &self.table_name
     }
 
-    pub fn before_first(&mut self) {
+    pub fn before_first(&mut self) -> DbResult<()> {
         self.move_to_block(0)
     }
 
-    pub fn next(&mut self) -> bool {
-        self.current_slot = self.rp.as_mut().unwrap().next_after(self.current_slot);
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> DbResult<bool> {
+        self.current_slot = self.rp.as_mut().unwrap().next_after(self.current_slot)?;
         while self.current_slot < 0 {
-            if self.at_last_block() {
-                return false;
+            if self.at_last_block()? {
+                return Ok(false);
             }
-            self.move_to_block(self.rp.clone().as_mut().unwrap().block().blk_num() + 1);
-            self.current_slot = self.rp.as_mut().unwrap().next_after(self.current_slot);
+            self.move_to_block(self.rp.clone().as_mut().unwrap().block().blk_num() + 1)?;
+            self.current_slot = self.rp.as_mut().unwrap().next_after(self.current_slot)?;
         }
-        true
+        Ok(true)
     }
 
-    pub fn get_int(&mut self, field_name: &str) -> u64 {
+    pub fn get_int(&mut self, field_name: &str) -> DbResult<i32> {
         self.rp.as_mut().unwrap().get_int(
             self.current_slot.try_into().unwrap(),
             field_name.to_string(),
         )
     }
 
-    pub fn get_int_u32(&mut self, field_name: &str) -> u32 {
-        self.current_slot += 1;
-        self.rp.as_mut().unwrap().get_int_u32(
-            self.current_slot.try_into().unwrap(),
-            field_name.to_string(),
-        )
-    }
-
-    pub fn get_string(&mut self, field_name: &str) -> String {
+    pub fn get_string(&mut self, field_name: &str) -> DbResult<String> {
         self.rp.as_mut().unwrap().get_string(
             self.current_slot.try_into().unwrap(),
             field_name.to_string(),
         )
     }
 
-    pub fn set_int(&mut self, field_name: &str, value: u64) {
+    pub fn set_int(&mut self, field_name: &str, value: i32) -> DbResult<()> {
         self.rp.as_mut().unwrap().set_int(
             self.current_slot.try_into().unwrap(),
             field_name.to_string(),
@@ -84,7 +79,7 @@ impl TableScan {
         )
     }
 
-    pub fn set_string(&mut self, field_name: &str, value: String) {
+    pub fn set_string(&mut self, field_name: &str, value: String) -> DbResult<()> {
         self.rp.as_mut().unwrap().set_string(
             self.current_slot.try_into().unwrap(),
             field_name.to_string(),
@@ -92,26 +87,27 @@ impl TableScan {
         )
     }
 
-    pub fn close(&mut self) {
-        match self.rp.clone() {
-            Some(mut rp) => self.tx.unpin(&mut rp.block()),
-            None => {}
-        }
+    // Ends the statement too, which releases its S locks under ReadCommitted.
+    pub fn close(&mut self) -> DbResult<()> {
+        self.unpin_current()?;
+        self.tx.end_statement();
+        Ok(())
     }
 
-    pub fn insert(&mut self) {
-        self.current_slot = self.rp.as_mut().unwrap().insert_after(self.current_slot);
+    pub fn insert(&mut self) -> DbResult<()> {
+        self.current_slot = self.rp.as_mut().unwrap().insert_after(self.current_slot)?;
         while self.current_slot < 0 {
-            if self.at_last_block() {
-                self.move_to_new_block()
+            if self.at_last_block()? {
+                self.move_to_new_block()?
             } else {
-                self.move_to_block(self.rp.clone().as_mut().unwrap().block().blk_num() + 1)
+                self.move_to_block(self.rp.clone().as_mut().unwrap().block().blk_num() + 1)?
             }
-            self.current_slot = self.rp.as_mut().unwrap().insert_after(self.current_slot);
+            self.current_slot = self.rp.as_mut().unwrap().insert_after(self.current_slot)?;
         }
+        Ok(())
     }
 
-    pub fn delete(&mut self) {
+    pub fn delete(&mut self) -> DbResult<()> {
         self.rp
             .as_mut()
             .unwrap()
@@ -125,32 +121,48 @@ impl TableScan {
         )
     }
 
-    fn move_to_block(&mut self, blk_num: u64) {
-        Self::close(self);
+    fn move_to_block(&mut self, blk_num: u64) -> DbResult<()> {
+        self.unpin_current()?;
         let mut blk = BlockId::new(self.file_name.to_string(), blk_num);
-        self.rp = Some(RecordPage::new(&mut self.tx, &mut blk, self.layout.clone()));
+        self.rp = Some(RecordPage::new(
+            &mut self.tx,
+            &mut blk,
+            self.layout.clone(),
+        )?);
         self.current_slot = -1;
+        Ok(())
+    }
+
+    fn move_to_new_block(&mut self) -> DbResult<()> {
+        self.unpin_current()?;
+        let mut blk = self.tx.append(&self.file_name)?;
+        self.rp = Some(RecordPage::new(
+            &mut self.tx,
+            &mut blk,
+            self.layout.clone(),
+        )?);
+        self.rp.as_mut().unwrap().format()?;
+        self.current_slot = -1;
+        Ok(())
     }
 
-    fn move_to_new_block(&mut self) {
-        Self::close(self);
-        let mut blk = self.tx.append(&self.file_name);
-        self.rp = Some(RecordPage::new(&mut self.tx, &mut blk, self.layout.clone()));
-        self.rp.as_mut().unwrap().format();
-        self.current_slot = -1;
+    // The statement goes on, so the locks taken so far are kept.
+    fn unpin_current(&mut self) -> DbResult<()> {
+        if let Some(rp) = self.rp.as_mut() {
+            self.tx.unpin(&mut rp.block())?;
+        }
+        Ok(())
     }
 
-    fn at_last_block(&mut self) -> bool {
-        self.rp.as_mut().unwrap().block().blk_num() == self.tx.size(&self.file_name) - 1
+    fn at_last_block(&mut self) -> DbResult<bool> {
+        Ok(self.rp.as_mut().unwrap().block().blk_num() == self.tx.size(&self.file_name)? - 1)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::buffer::buffer_mgr::BufferMgr;
-    use crate::file::file_mgr::FileMgr;
-    use crate::logging::log_mgr::LogMgr;
     use crate::record::schema::Schema;
+    use crate::server::simple_db::SimpleDB;
 
     use super::*;
     use std::fs;
@@ -160,34 +172,30 @@ mod tests {
         if Path::new(&db_dir.to_string()).exists() {
             fs::remove_dir_all(db_dir).unwrap();
         }
-        let block_size = 400;
-        let mut fm = FileMgr::new(db_dir.to_string(), block_size);
-        let log_file = "ruspledb.log";
-        let mut lm = LogMgr::new(&mut fm, &mut log_file.to_string());
-        let buffer_size = 8;
-        let mut bm = BufferMgr::new(&mut fm, &mut lm, buffer_size);
-        let mut tx = Transaction::new(&mut fm, &mut lm, &mut bm);
+        let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+        let mut tx = db.new_tx().unwrap();
 
         let mut schema = Schema::new();
         schema.add_int_field("A");
         schema.add_string_field("B", 9);
-        let layout = Layout::new(&mut schema);
-        TableScan::new(&mut tx, "T", layout)
+        let layout = Layout::new(&mut schema).unwrap();
+        TableScan::new(&mut tx, "T", layout).unwrap()
     }
 
     #[test]
     fn unit_get_int() {
         let mut ts = prepare_ts("./db/tablescanunittest_1");
-        ts.insert();
-        ts.set_int("A", 25);
-        ts.set_int("A", 24);
-        ts.set_int("A", 23);
+        ts.insert().unwrap();
+        ts.set_int("A", 25).unwrap();
+        ts.set_int("A", 24).unwrap();
+        ts.set_int("A", 23).unwrap();
 
-        ts.before_first();
+        ts.before_first().unwrap();
+        assert!(ts.next().unwrap());
 
-        let mut a = ts.get_int("A");
+        let mut a = ts.get_int("A").unwrap();
         assert_eq!(a, 23);
-        a = ts.get_int("A");
+        a = ts.get_int("A").unwrap();
         assert_eq!(a, 23);
     }
 }
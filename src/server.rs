@@ -0,0 +1 @@
+pub mod simple_db;
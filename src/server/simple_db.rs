@@ -0,0 +1,269 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{
+    buffer::buffer_mgr::{BufferMgr, ReplacementPolicy},
+    error::DbResult,
+    file::file_mgr::FileMgr,
+    logging::log_mgr::{LogMgr, LogRetention},
+    tx::{
+        concurrency::{
+            concurrency_mgr::{ConcurrencyMode, IsolationLevel},
+            lock_table::{DeadlockStrategy, LockTable},
+            version_store::VersionStore,
+        },
+        recovery::{
+            checkpoint_record::CheckpointRecord,
+            nq_checkpoint_record::NqCheckpointRecord,
+            recovery_mgr::{InDoubtTx, RecoveryMgr, RecoveryMode},
+        },
+        transaction::Transaction,
+        tx_num_allocator::TxNumAllocator,
+        tx_registry::TxRegistry,
+    },
+};
+
+pub const BLOCK_SIZE: u64 = 400;
+pub const BUFFER_SIZE: usize = 8;
+pub const LOG_FILE: &str = "ruspledb.log";
+
+// The engine owns the single file, log and buffer manager of a database
+// and hands out transactions that all work on them.
+pub struct SimpleDB {
+    fm: Arc<FileMgr>,
+    lm: Arc<Mutex<LogMgr>>,
+    bm: Arc<BufferMgr>,
+    tx_registry: Arc<TxRegistry>,
+    tx_nums: TxNumAllocator,
+    log_retention: LogRetention,
+    recovery_mode: RecoveryMode,
+    lock_tbl: Arc<LockTable>,
+    version_store: Option<Arc<VersionStore>>,
+    // prepared transactions restored at startup
+    in_doubt: Vec<Transaction>,
+}
+
+impl SimpleDB {
+    pub fn new(db_dir: &str, block_size: u64, buffer_size: usize) -> DbResult<Self> {
+        Self::with_file_mgr(FileMgr::new(db_dir.to_string(), block_size)?, buffer_size)
+    }
+
+    // Runs the engine on a file manager set up by the caller, e.g. one with checksums.
+    pub fn with_file_mgr(fm: FileMgr, buffer_size: usize) -> DbResult<Self> {
+        Self::with_buffer_policy(fm, buffer_size, ReplacementPolicy::Naive)
+    }
+
+    pub fn with_buffer_policy(
+        fm: FileMgr,
+        buffer_size: usize,
+        policy: ReplacementPolicy,
+    ) -> DbResult<Self> {
+        let fm = Arc::new(fm);
+        let lm = Arc::new(Mutex::new(LogMgr::new(
+            fm.clone(),
+            &mut LOG_FILE.to_string(),
+        )?));
+        let bm = Arc::new(BufferMgr::with_policy(
+            fm.clone(),
+            lm.clone(),
+            buffer_size,
+            policy,
+        ));
+        let tx_nums = TxNumAllocator::resume(&mut lm.lock().unwrap())?;
+        let mut db = SimpleDB {
+            fm,
+            lm,
+            bm,
+            tx_registry: Arc::new(TxRegistry::new()),
+            tx_nums,
+            log_retention: LogRetention::Truncate,
+            recovery_mode: RecoveryMode::UndoOnly,
+            lock_tbl: Arc::new(LockTable::new()),
+            version_store: None,
+            in_doubt: vec![],
+        };
+        let mut tx = db.new_tx()?;
+        let in_doubt = match db.fm.is_new() {
+            true => vec![],
+            false => tx.recover()?,
+        };
+        tx.commit()?;
+        db.in_doubt = in_doubt
+            .into_iter()
+            .map(|t| db.restore(t))
+            .collect::<DbResult<_>>()?;
+        Ok(db)
+    }
+
+    pub fn new_tx(&self) -> DbResult<Transaction> {
+        self.new_tx_with_isolation(IsolationLevel::Serializable)
+    }
+
+    pub fn new_tx_with_isolation(&self, isolation: IsolationLevel) -> DbResult<Transaction> {
+        let tx_num = self.tx_nums.next();
+        // waits while a checkpoint is being taken
+        let recovery_mgr = self.tx_registry.start(tx_num, || {
+            let rm = RecoveryMgr::new(
+                tx_num,
+                self.fm.clone(),
+                self.lm.clone(),
+                self.bm.clone(),
+                self.recovery_mode,
+            )?;
+            let segment = rm.start_segment();
+            Ok((rm, segment))
+        })?;
+        Ok(self.transaction(tx_num, recovery_mgr, isolation))
+    }
+
+    // The prepared transactions found in doubt at startup that are still
+    // waiting for commit_prepared or rollback_prepared.
+    pub fn in_doubt_txs(&self) -> Vec<Transaction> {
+        let active = self.tx_registry.active_txs();
+        self.in_doubt
+            .iter()
+            .cloned()
+            .filter_map(|mut tx| active.contains(&tx.tx_num()).then_some(tx))
+            .collect()
+    }
+
+    // What checkpoints do with the log segments before them; truncated by default.
+    pub fn set_log_retention(&mut self, log_retention: LogRetention) {
+        self.log_retention = log_retention;
+    }
+
+    // Applies to transactions started afterwards. Restart recovery both undoes
+    // and redoes, so a database may be reopened in either mode.
+    pub fn set_recovery_mode(&mut self, recovery_mode: RecoveryMode) {
+        self.recovery_mode = recovery_mode;
+    }
+
+    // How long a pin waits for a free buffer before giving up.
+    pub fn set_buffer_timeout(&self, max_time: Duration) {
+        self.bm.set_max_time(max_time);
+    }
+
+    // How long a transaction waits for a lock before giving up.
+    pub fn set_lock_timeout(&self, max_time: Duration) {
+        self.lock_tbl.set_max_time(max_time);
+    }
+
+    // Pick the strategy before handing out transactions.
+    pub fn set_deadlock_strategy(&mut self, strategy: DeadlockStrategy) {
+        self.lock_tbl.set_strategy(strategy);
+    }
+
+    // Pick the mode before handing out transactions.
+    pub fn set_concurrency_mode(&mut self, mode: ConcurrencyMode) {
+        self.version_store = match mode {
+            ConcurrencyMode::Locking => None,
+            ConcurrencyMode::Mvcc => Some(Arc::new(VersionStore::new())),
+        };
+    }
+
+    pub fn version_store(&self) -> Option<Arc<VersionStore>> {
+        self.version_store.clone()
+    }
+
+    pub fn lock_table(&self) -> Arc<LockTable> {
+        self.lock_tbl.clone()
+    }
+
+    // Quiescent checkpoint: hold back new transactions, wait for the running
+    // ones to finish, then flush everything and write <CHECKPOINT>
+    // at the start of a new log segment.
+    // Must not be called by a thread that still has a transaction open.
+    pub fn checkpoint(&self) -> DbResult<()> {
+        self.tx_registry.pause(true);
+        let res = self.write_checkpoint(|lm| {
+            let segment = lm.rotate()?;
+            let lsn = CheckpointRecord::write_to_log(lm, self.tx_nums.last())?;
+            lm.flush_with_lsn(lsn)?;
+            lm.discard_before(segment, self.log_retention)
+        });
+        self.tx_registry.resume();
+        res
+    }
+
+    // Non-quiescent checkpoint: only hold back new transactions while the
+    // buffers are flushed and <NQCKPT T1, ..., Tk> is written for the running ones.
+    // Segments still holding the START of a running transaction are kept.
+    pub fn nq_checkpoint(&self) -> DbResult<()> {
+        self.tx_registry.pause(false);
+        let res = self.write_checkpoint(|lm| {
+            // taken while the log is held, so that whoever isn't listed wrote
+            // its COMMIT or ROLLBACK before the record; a listed one may have
+            // too, which recovery allows for
+            let active_txs = self.tx_registry.active_txs();
+            let oldest = self.tx_registry.oldest_segment();
+            let segment = lm.rotate()?;
+            let lsn = NqCheckpointRecord::write_to_log(lm, self.tx_nums.last(), active_txs)?;
+            lm.flush_with_lsn(lsn)?;
+            lm.discard_before(oldest.unwrap_or(segment), self.log_retention)
+        });
+        self.tx_registry.resume();
+        res
+    }
+
+    pub fn active_txs(&self) -> Vec<i32> {
+        self.tx_registry.active_txs()
+    }
+
+    // Flushes every buffer and hands the log to `write` while new
+    // transactions are held back. No page changes in between, or a change
+    // logged before the record would be neither flushed nor redone.
+    fn write_checkpoint(&self, write: impl FnOnce(&mut LogMgr) -> DbResult<()>) -> DbResult<()> {
+        let _held = self.tx_registry.hold_updates();
+        self.bm.flush_all_buffers()?;
+        write(&mut self.lm.lock().unwrap())
+    }
+
+    // Brings an in-doubt transaction back under its own number without
+    // logging another START.
+    fn restore(&self, in_doubt: InDoubtTx) -> DbResult<Transaction> {
+        let recovery_mgr = self.tx_registry.start(in_doubt.tx_num, || {
+            let rm = RecoveryMgr::restore(
+                in_doubt.tx_num,
+                self.fm.clone(),
+                self.lm.clone(),
+                self.bm.clone(),
+                self.recovery_mode,
+                in_doubt.start_segment,
+            );
+            Ok((rm, in_doubt.start_segment))
+        })?;
+        let mut tx = self.transaction(in_doubt.tx_num, recovery_mgr, IsolationLevel::Serializable);
+        tx.restore_prepared(in_doubt.blocks)?;
+        Ok(tx)
+    }
+
+    fn transaction(
+        &self,
+        tx_num: i32,
+        recovery_mgr: RecoveryMgr,
+        isolation: IsolationLevel,
+    ) -> Transaction {
+        Transaction::new(
+            tx_num,
+            self.fm.clone(),
+            self.bm.clone(),
+            self.tx_registry.clone(),
+            recovery_mgr,
+            self.lock_tbl.clone(),
+            self.version_store.clone(),
+            isolation,
+        )
+    }
+
+    pub fn file_mgr(&self) -> Arc<FileMgr> {
+        self.fm.clone()
+    }
+
+    pub fn log_mgr(&self) -> Arc<Mutex<LogMgr>> {
+        self.lm.clone()
+    }
+
+    pub fn buffer_mgr(&self) -> Arc<BufferMgr> {
+        self.bm.clone()
+    }
+}
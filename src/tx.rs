@@ -2,3 +2,5 @@ pub mod buffer_list;
 pub mod concurrency;
 pub mod recovery;
 pub mod transaction;
+pub mod tx_num_allocator;
+pub mod tx_registry;
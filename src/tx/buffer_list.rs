@@ -1,54 +1,55 @@
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 use crate::{
     buffer::{buffer::Buffer, buffer_mgr::BufferMgr},
+    error::{DbError, DbResult},
     file::block_id::BlockId,
 };
 
-#[derive(Clone)]
 pub struct BufferList {
-    bm: BufferMgr,
-    pins: Vec<BlockId>,
-    buffers: HashMap<BlockId, Buffer>,
+    bm: Arc<BufferMgr>,
+    // each pinned block's buffer and how many times it is pinned
+    buffers: HashMap<BlockId, (Arc<Mutex<Buffer>>, usize)>,
 }
 
 impl BufferList {
-    pub fn new(bm: &mut BufferMgr) -> Self {
+    pub fn new(bm: Arc<BufferMgr>) -> Self {
         BufferList {
-            bm: bm.to_owned(),
-            pins: vec![],
+            bm,
             buffers: HashMap::new(),
         }
     }
 
-    pub fn get_buffer(&mut self, blk: &mut BlockId) -> Option<&Buffer> {
-        self.buffers.get(blk)
+    pub fn get_buffer(&mut self, blk: &mut BlockId) -> Option<Arc<Mutex<Buffer>>> {
+        self.buffers.get(blk).map(|(buffer, _)| buffer.clone())
     }
 
-    pub fn set_buffer(&mut self, blk: BlockId, buffer: Buffer) -> Option<Buffer> {
-        self.buffers.insert(blk, buffer)
+    pub fn pin(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        let buffer = self.bm.pin(blk)?;
+        self.buffers.entry(blk.to_owned()).or_insert((buffer, 0)).1 += 1;
+        Ok(())
     }
 
-    pub fn pin(&mut self, blk: &mut BlockId) {
-        let buffer = self.bm.pin(blk).unwrap();
-        self.buffers.insert(blk.to_owned(), buffer);
-        self.pins.append(&mut vec![blk.to_owned()]);
-    }
-
-    pub fn unpin(&mut self, blk: &mut BlockId) {
-        let mut buffer = self.buffers.get(blk).unwrap().to_owned();
-        self.bm.unpin(&mut buffer);
-        if let Some(remove_index) = self.pins.iter().position(|pin| *pin == blk.to_owned()) {
-            self.pins.remove(remove_index);
+    pub fn unpin(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        let (buffer, pins) = self
+            .buffers
+            .get_mut(blk)
+            .ok_or_else(|| DbError::NotPinned(blk.clone()))?;
+        self.bm.unpin(buffer);
+        *pins -= 1;
+        if *pins == 0 {
+            self.buffers.remove(blk);
         }
+        Ok(())
     }
 
     pub fn unpin_all(&mut self) {
-        for blk in self.pins.iter_mut() {
-            let buffer = self.buffers.get(blk).unwrap();
-            self.bm.unpin(&mut buffer.to_owned());
+        for (buffer, pins) in self.buffers.values() {
+            for _ in 0..*pins {
+                self.bm.unpin(buffer);
+            }
         }
         self.buffers.clear();
-        self.pins.clear();
     }
 }
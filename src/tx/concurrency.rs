@@ -1,2 +1,3 @@
 pub mod concurrency_mgr;
 pub mod lock_table;
+pub mod version_store;
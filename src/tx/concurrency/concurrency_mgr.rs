@@ -1,43 +1,105 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use crate::{file::block_id::BlockId, tx::concurrency::lock_table::LockTable};
+use crate::{error::DbResult, file::block_id::BlockId, tx::concurrency::lock_table::LockTable};
 
-#[derive(Clone)]
+// Under Mvcc readers take no locks and see a snapshot instead;
+// writers still take X locks.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ConcurrencyMode {
+    Locking,
+    Mvcc,
+}
+
+// When a transaction's S locks are released. ReadUncommitted takes none,
+// ReadCommitted drops them at the end of each statement, the others keep them
+// until the transaction ends. Serializable also S-locks the end of a file
+// whenever it asks for the file's size, which keeps out phantoms.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+// The locks held by one transaction, taken from the shared lock table.
 pub struct ConcurrencyMgr {
-    lock_tbl: LockTable,
+    tx_num: i32,
+    isolation: IsolationLevel,
+    lock_tbl: Arc<LockTable>,
     locks: HashMap<BlockId, String>,
 }
 
 impl ConcurrencyMgr {
-    pub fn new() -> Self {
+    pub fn new(tx_num: i32, isolation: IsolationLevel, lock_tbl: Arc<LockTable>) -> Self {
         ConcurrencyMgr {
-            lock_tbl: LockTable::new(),
+            tx_num,
+            isolation,
+            lock_tbl,
             locks: HashMap::new(),
         }
     }
 
-    pub fn s_lock(&mut self, blk: &mut BlockId) {
-        match self.locks.get(blk) {
-            Some(_) => self.lock_tbl.s_lock(blk).unwrap(),
-            None => {}
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    pub fn s_lock(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        if self.isolation == IsolationLevel::ReadUncommitted {
+            return Ok(());
         }
+        self.take_s_lock(blk)
     }
 
-    pub fn x_lock(&mut self, blk: &mut BlockId) {
+    pub fn x_lock(&mut self, blk: &mut BlockId) -> DbResult<()> {
         if !Self::has_x_lock(self, blk) {
-            Self::s_lock(self, blk);
+            self.take_s_lock(blk)?;
+            self.lock_tbl.x_lock(self.tx_num, blk)?;
+            self.locks.insert(blk.to_owned(), "X".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_wounded(&self) -> bool {
+        self.lock_tbl.is_wounded(self.tx_num)
+    }
+
+    // Under ReadCommitted the S locks of a finished statement are released.
+    pub fn end_statement(&mut self) {
+        if self.isolation != IsolationLevel::ReadCommitted {
+            return;
+        }
+        let shared: Vec<BlockId> = self
+            .locks
+            .iter()
+            .filter(|(_, lock_type)| lock_type.eq(&"S"))
+            .map(|(blk, _)| blk.to_owned())
+            .collect();
+        for mut blk in shared {
+            self.lock_tbl.unlock(self.tx_num, &mut blk);
+            self.locks.remove(&blk);
+        }
+    }
+
+    fn take_s_lock(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        if !self.locks.contains_key(blk) {
+            self.lock_tbl.s_lock(self.tx_num, blk)?;
+            self.locks.insert(blk.to_owned(), "S".to_string());
         }
+        Ok(())
     }
 
     pub fn release(&mut self) {
         for blk in self.locks.keys() {
-            self.lock_tbl.unlock(&mut blk.to_owned())
+            self.lock_tbl.unlock(self.tx_num, &mut blk.to_owned())
         }
         self.locks.clear();
+        self.lock_tbl.finish(self.tx_num);
     }
 
     fn has_x_lock(&mut self, blk: &mut BlockId) -> bool {
-        let lock_type = self.locks.get(&blk);
+        let lock_type = self.locks.get(blk);
         match lock_type {
             Some(lock_type) => lock_type.eq("X"),
             None => false,
@@ -1,71 +1,187 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::file::block_id::BlockId;
-use std::process::Command;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{
+    error::{DbError, DbResult},
+    file::block_id::BlockId,
+};
 
-#[derive(Clone)]
+// How conflicting lock requests are kept from deadlocking. Transaction numbers
+// serve as timestamps: the lower the number, the older the transaction.
+// The timeout applies under every strategy.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeadlockStrategy {
+    // wait until the lock is free or the timeout runs out
+    Timeout,
+    // an older requester waits, a younger one aborts
+    WaitDie,
+    // an older requester aborts the younger holders, a younger one waits;
+    // a wounded transaction aborts at its next lock request or commit
+    WoundWait,
+    // wait unless that closes a cycle in the waits-for graph
+    WaitsForGraph,
+}
+
+#[derive(Default)]
+struct Lock {
+    holders: BTreeSet<i32>,
+    exclusive: bool,
+}
+
+struct LockState {
+    strategy: DeadlockStrategy,
+    locks: HashMap<BlockId, Lock>,
+    waits_for: HashMap<i32, HashSet<i32>>,
+    wounded: HashSet<i32>,
+}
+
+// One lock table is shared by every transaction of a database.
 pub struct LockTable {
-    max_time: u64,
-    locks: HashMap<BlockId, usize>,
+    max_time: AtomicU64,
+    state: Mutex<LockState>,
+    cond: Condvar,
+}
+
+impl Default for LockTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LockTable {
     pub fn new() -> Self {
         LockTable {
-            max_time: 10000,
-            locks: HashMap::new(),
+            max_time: AtomicU64::new(10000),
+            state: Mutex::new(LockState {
+                strategy: DeadlockStrategy::Timeout,
+                locks: HashMap::new(),
+                waits_for: HashMap::new(),
+                wounded: HashSet::new(),
+            }),
+            cond: Condvar::new(),
         }
     }
 
-    pub fn s_lock(&mut self, blk: &mut BlockId) -> Result<(), String> {
-        let start = SystemTime::now();
-        let since_the_epoch = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards");
-        let timestamp =
-            since_the_epoch.as_secs() * 1000 + since_the_epoch.subsec_nanos() as u64 / 1_000_000;
-        while Self::has_x_lock(self, blk) && !Self::wait_too_long(self, timestamp) {
-            let mut child = Command::new("sleep").arg(10.to_string()).spawn().unwrap();
-            child.wait().unwrap();
-        }
-        if Self::has_x_lock(self, blk) {
-            return Err("LockAbortException".to_string());
-        }
-        let value = self.get_lock_val(blk);
-        self.locks.insert(blk.to_owned(), value + 1);
-        Ok(())
+    // How long a lock request may wait before it gives up with LockAbort.
+    pub fn set_max_time(&self, max_time: Duration) {
+        self.max_time
+            .store(max_time.as_millis().try_into().unwrap(), Ordering::SeqCst)
     }
 
-    pub fn unlock(&mut self, blk: &mut BlockId) {
-        let value = self.get_lock_val(blk);
-        if value > 1 {
-            self.locks.insert(blk.to_owned(), value - 1);
-        } else {
-            self.locks.remove(blk);
-            // notifyAll();
+    pub fn set_strategy(&self, strategy: DeadlockStrategy) {
+        self.state.lock().unwrap().strategy = strategy;
+    }
+
+    pub fn s_lock(&self, tx_num: i32, blk: &mut BlockId) -> DbResult<()> {
+        self.acquire(tx_num, blk, false)
+    }
+
+    // The caller must already hold an S lock on the block, which gets upgraded
+    // once no other transaction holds one.
+    pub fn x_lock(&self, tx_num: i32, blk: &mut BlockId) -> DbResult<()> {
+        self.acquire(tx_num, blk, true)
+    }
+
+    pub fn unlock(&self, tx_num: i32, blk: &mut BlockId) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(lock) = state.locks.get_mut(blk) {
+            lock.holders.remove(&tx_num);
+            if lock.holders.is_empty() {
+                state.locks.remove(blk);
+            }
         }
+        // an upgrade may be waiting for the last other S lock to go
+        self.cond.notify_all();
+    }
+
+    // Whether an older transaction has wounded this one, which may then
+    // no longer commit.
+    pub fn is_wounded(&self, tx_num: i32) -> bool {
+        self.state.lock().unwrap().wounded.contains(&tx_num)
+    }
+
+    // Forgets what the deadlock strategy knew about a finished transaction.
+    pub fn finish(&self, tx_num: i32) {
+        let mut state = self.state.lock().unwrap();
+        state.wounded.remove(&tx_num);
+        state.waits_for.remove(&tx_num);
     }
 
-    fn has_x_lock(&mut self, blk: &mut BlockId) -> bool {
-        Self::get_lock_val(self, blk) < 0
+    fn acquire(&self, tx_num: i32, blk: &mut BlockId, exclusive: bool) -> DbResult<()> {
+        let deadline = Instant::now() + Duration::from_millis(self.max_time.load(Ordering::SeqCst));
+        let mut state = self.state.lock().unwrap();
+        loop {
+            // checked before the blockers, or a wounded transaction could go on
+            // taking free locks while the older one waits for its
+            if state.wounded.contains(&tx_num) {
+                state.waits_for.remove(&tx_num);
+                return Err(DbError::LockAbort);
+            }
+            let blockers = Self::blockers(&state, tx_num, blk, exclusive);
+            if blockers.is_empty() {
+                break;
+            }
+            let abort = match state.strategy {
+                DeadlockStrategy::Timeout => false,
+                DeadlockStrategy::WaitDie => blockers.iter().any(|holder| *holder < tx_num),
+                DeadlockStrategy::WoundWait => {
+                    for holder in blockers.iter().filter(|holder| **holder > tx_num) {
+                        state.wounded.insert(*holder);
+                    }
+                    // wake up wounded transactions that are waiting themselves
+                    self.cond.notify_all();
+                    false
+                }
+                DeadlockStrategy::WaitsForGraph => {
+                    state.waits_for.insert(tx_num, blockers);
+                    Self::waits_for_itself(&state, tx_num)
+                }
+            };
+            let now = Instant::now();
+            if abort || now >= deadline {
+                state.waits_for.remove(&tx_num);
+                return Err(DbError::LockAbort);
+            }
+            state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
+        }
+        state.waits_for.remove(&tx_num);
+        let lock = state.locks.entry(blk.to_owned()).or_default();
+        lock.holders.insert(tx_num);
+        lock.exclusive |= exclusive;
+        Ok(())
     }
 
-    fn get_lock_val(&mut self, blk: &mut BlockId) -> usize {
-        let i_val = self.locks.get(blk);
-        match i_val {
-            Some(val) => *val,
-            None => 0,
+    // The other transactions whose locks keep this request from being granted.
+    fn blockers(state: &LockState, tx_num: i32, blk: &BlockId, exclusive: bool) -> HashSet<i32> {
+        match state.locks.get(blk) {
+            Some(lock) if exclusive || lock.exclusive => lock
+                .holders
+                .iter()
+                .copied()
+                .filter(|holder| *holder != tx_num)
+                .collect(),
+            _ => HashSet::new(),
         }
     }
 
-    fn wait_too_long(&mut self, start_time: u64) -> bool {
-        let start = SystemTime::now();
-        let since_the_epoch = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards");
-        let timestamp =
-            since_the_epoch.as_secs() * 1000 + since_the_epoch.subsec_nanos() as u64 / 1_000_000;
-        timestamp - start_time > self.max_time
+    fn waits_for_itself(state: &LockState, tx_num: i32) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack: Vec<i32> = match state.waits_for.get(&tx_num) {
+            Some(holders) => holders.iter().copied().collect(),
+            None => return false,
+        };
+        while let Some(waiter) = stack.pop() {
+            if waiter == tx_num {
+                return true;
+            }
+            if visited.insert(waiter) {
+                if let Some(holders) = state.waits_for.get(&waiter) {
+                    stack.extend(holders.iter().copied());
+                }
+            }
+        }
+        false
     }
 }
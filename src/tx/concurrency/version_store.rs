@@ -0,0 +1,178 @@
+use bytebuffer::ByteBuffer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    buffer::buffer::Buffer,
+    error::{DbError, DbResult},
+    file::{block_id::BlockId, page::Page},
+};
+
+// The contents of a block before `tx_num` changed it. Once the writer commits,
+// the image is what snapshots taken before `commit_ts` see.
+struct Version {
+    tx_num: i32,
+    commit_ts: Option<u64>,
+    image: Vec<u8>,
+}
+
+#[derive(Default)]
+struct VersionState {
+    last_commit_ts: u64,
+    // running transactions and their snapshot timestamps
+    snapshots: HashMap<i32, u64>,
+    versions: HashMap<BlockId, Vec<Version>>,
+    written: HashMap<i32, Vec<BlockId>>,
+}
+
+// Keeps the older versions of blocks so that MVCC readers see the database
+// as of their snapshot without taking any locks.
+#[derive(Default)]
+pub struct VersionStore {
+    state: Mutex<VersionState>,
+}
+
+impl VersionStore {
+    pub fn new() -> Self {
+        VersionStore::default()
+    }
+
+    // Registers a transaction and returns its snapshot timestamp.
+    pub fn begin(&self, tx_num: i32) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let snapshot = state.last_commit_ts;
+        state.snapshots.insert(tx_num, snapshot);
+        snapshot
+    }
+
+    // Reads the block as of the snapshot: the oldest image that was replaced
+    // after the snapshot, or the buffer itself if there is none.
+    pub fn read<R>(
+        &self,
+        tx_num: i32,
+        blk: &BlockId,
+        buffer: &Arc<Mutex<Buffer>>,
+        f: impl FnOnce(&Page) -> R,
+    ) -> R {
+        let state = self.state.lock().unwrap();
+        let snapshot = state.snapshots[&tx_num];
+        let mut visible: Option<&Version> = None;
+        let own_write = state
+            .written
+            .get(&tx_num)
+            .is_some_and(|blks| blks.contains(blk));
+        if !own_write {
+            for version in state.versions.get(blk).into_iter().flatten() {
+                let replaced_after = version.commit_ts.is_none_or(|ts| ts > snapshot);
+                let older = match visible {
+                    None => true,
+                    Some(v) => match (version.commit_ts, v.commit_ts) {
+                        (Some(ts), Some(v_ts)) => ts < v_ts,
+                        (Some(_), None) => true,
+                        _ => false,
+                    },
+                };
+                if replaced_after && older {
+                    visible = Some(version);
+                }
+            }
+        }
+        match visible {
+            Some(version) => {
+                let page = Page::new_from_buffer(&mut ByteBuffer::from_bytes(&version.image));
+                f(&page)
+            }
+            None => {
+                let latch = buffer.lock().unwrap().latch();
+                let page = latch.read().unwrap();
+                f(&page)
+            }
+        }
+    }
+
+    // Keeps the current contents of the block before the transaction first changes it.
+    // The caller holds the X lock on the block.
+    pub fn before_write(&self, tx_num: i32, blk: &BlockId, buffer: &Arc<Mutex<Buffer>>) {
+        let mut state = self.state.lock().unwrap();
+        let written = state.written.entry(tx_num).or_default();
+        if written.contains(blk) {
+            return;
+        }
+        written.push(blk.to_owned());
+        let latch = buffer.lock().unwrap().latch();
+        let image = latch.read().unwrap().buffer.as_bytes().to_vec();
+        state
+            .versions
+            .entry(blk.to_owned())
+            .or_default()
+            .push(Version {
+                tx_num,
+                commit_ts: None,
+                image,
+            });
+    }
+
+    // Fails with WriteConflict if another transaction committed a change to one
+    // of the blocks this one wrote after its snapshot was taken; the first
+    // committer wins.
+    pub fn validate(&self, tx_num: i32) -> DbResult<()> {
+        let state = self.state.lock().unwrap();
+        let snapshot = state.snapshots[&tx_num];
+        for blk in state.written.get(&tx_num).into_iter().flatten() {
+            let conflict = state.versions[blk]
+                .iter()
+                .any(|v| v.tx_num != tx_num && v.commit_ts.is_some_and(|ts| ts > snapshot));
+            if conflict {
+                return Err(DbError::WriteConflict);
+            }
+        }
+        Ok(())
+    }
+
+    // Makes the transaction's changes visible to snapshots taken from now on.
+    pub fn commit(&self, tx_num: i32) {
+        let mut state = self.state.lock().unwrap();
+        state.last_commit_ts += 1;
+        let commit_ts = state.last_commit_ts;
+        let written = state.written.remove(&tx_num).unwrap_or_default();
+        for blk in written {
+            for version in state.versions.get_mut(&blk).unwrap() {
+                if version.tx_num == tx_num {
+                    version.commit_ts = Some(commit_ts);
+                }
+            }
+        }
+        state.snapshots.remove(&tx_num);
+        Self::collect_garbage(&mut state);
+    }
+
+    // Drops the images of a rolled back transaction; its changes are undone by now.
+    pub fn abort(&self, tx_num: i32) {
+        let mut state = self.state.lock().unwrap();
+        let written = state.written.remove(&tx_num).unwrap_or_default();
+        for blk in written {
+            let versions = state.versions.get_mut(&blk).unwrap();
+            versions.retain(|v| v.tx_num != tx_num);
+            if versions.is_empty() {
+                state.versions.remove(&blk);
+            }
+        }
+        state.snapshots.remove(&tx_num);
+        Self::collect_garbage(&mut state);
+    }
+
+    // The number of block images kept.
+    pub fn version_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.versions.values().map(|versions| versions.len()).sum()
+    }
+
+    // An image committed at ts is only needed by snapshots older than ts.
+    fn collect_garbage(state: &mut VersionState) {
+        let oldest = state.snapshots.values().min().copied().unwrap_or(u64::MAX);
+        state.versions.retain(|_, versions| {
+            versions.retain(|v| v.commit_ts.is_none_or(|ts| ts > oldest));
+            !versions.is_empty()
+        });
+    }
+}
@@ -1,5 +1,12 @@
+pub mod checkpoint_record;
 pub mod commit_record;
 pub mod log_record;
+pub mod nq_checkpoint_record;
+pub mod page_image_record;
+pub mod prepare_record;
 pub mod recovery_mgr;
+pub mod rollback_record;
+pub mod savepoint_record;
 pub mod set_int_record;
 pub mod set_string_record;
+pub mod start_record;
@@ -0,0 +1,46 @@
+use bytebuffer::ByteBuffer;
+
+use crate::{error::DbResult, file::page::Page, logging::log_mgr::LogMgr};
+
+use super::log_record::{LogRecord, CHECKPOINT};
+
+pub struct CheckpointRecord {
+    max_tx_num: i32,
+}
+
+impl CheckpointRecord {
+    pub fn new(page: Page) -> DbResult<Self> {
+        let max_tx_num = page.get_i32(8)?;
+        Ok(CheckpointRecord { max_tx_num })
+    }
+
+    pub fn write_to_log(lm: &mut LogMgr, max_tx_num: i32) -> DbResult<i64> {
+        lm.reset_page_images();
+        lm.append(CheckpointRecord { max_tx_num }.encode()?)
+    }
+
+    // The highest transaction number handed out when the checkpoint was taken.
+    pub fn max_tx_num(&self) -> i32 {
+        self.max_tx_num
+    }
+}
+
+impl LogRecord for CheckpointRecord {
+    fn op(&self) -> u64 {
+        CHECKPOINT
+    }
+
+    fn tx_number(&self) -> i32 {
+        // dummy value
+        -1
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>> {
+        let mut rec = ByteBuffer::new();
+        rec.resize(8 + 4);
+        let mut p = Page::new_from_buffer(&mut rec);
+        p.set_u64(0, CHECKPOINT)?;
+        p.set_i32(8, self.max_tx_num)?;
+        Ok(p.buffer.into_vec())
+    }
+}
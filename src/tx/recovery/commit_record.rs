@@ -1,17 +1,40 @@
 use bytebuffer::ByteBuffer;
 
-use crate::{file::page::Page, logging::log_mgr::LogMgr};
+use crate::{error::DbResult, file::page::Page, logging::log_mgr::LogMgr};
 
-pub struct CommitRecord {}
+use super::log_record::{LogRecord, COMMIT};
+
+pub struct CommitRecord {
+    tx_num: i32,
+}
 
 impl CommitRecord {
-    pub fn write_to_log(lm: &mut LogMgr, tx_num: i32) -> i64 {
+    pub fn new(page: Page) -> DbResult<Self> {
+        let t_pos = 8;
+        let tx_num = page.get_i32(t_pos)?;
+        Ok(CommitRecord { tx_num })
+    }
+
+    pub fn write_to_log(lm: &mut LogMgr, tx_num: i32) -> DbResult<i64> {
+        lm.append(CommitRecord { tx_num }.encode()?)
+    }
+}
+
+impl LogRecord for CommitRecord {
+    fn op(&self) -> u64 {
+        COMMIT
+    }
+
+    fn tx_number(&self) -> i32 {
+        self.tx_num
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>> {
         let mut rec = ByteBuffer::new();
-        rec.resize(2 * 4);
+        rec.resize(8 + 4);
         let mut p = Page::new_from_buffer(&mut rec);
-        // SETINT = 2
-        p.set_u64(0, 2);
-        p.set_i32(4, tx_num);
-        lm.append(rec.into_bytes())
+        p.set_u64(0, COMMIT)?;
+        p.set_i32(8, self.tx_num)?;
+        Ok(p.buffer.into_vec())
     }
 }
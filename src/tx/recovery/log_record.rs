@@ -1,17 +1,104 @@
 use bytebuffer::ByteBuffer;
+use std::any::Any;
 
-use crate::file::page::Page;
+use crate::{
+    error::{DbError, DbResult},
+    file::{block_id::BlockId, page::Page},
+    logging::log_iterator::LogIterator,
+    tx::transaction::Transaction,
+};
 
-use super::{set_int_record::SetIntRecord, set_string_record::SetStringRecord};
+use super::{
+    checkpoint_record::CheckpointRecord, commit_record::CommitRecord,
+    nq_checkpoint_record::NqCheckpointRecord, page_image_record::PageImageRecord,
+    prepare_record::PrepareRecord, rollback_record::RollbackRecord,
+    savepoint_record::SavepointRecord, set_int_record::SetIntRecord,
+    set_string_record::SetStringRecord, start_record::StartRecord,
+};
 
-pub struct LogRecord {}
+pub const CHECKPOINT: u64 = 0;
+pub const START: u64 = 1;
+pub const COMMIT: u64 = 2;
+pub const ROLLBACK: u64 = 3;
+pub const SETINT: u64 = 4;
+pub const SETSTRING: u64 = 5;
+pub const NQCKPT: u64 = 6;
+pub const SAVEPOINT: u64 = 7;
+pub const PREPARE: u64 = 8;
+pub const PAGEIMAGE: u64 = 9;
 
-impl LogRecord {
-    // pub fn create_log_record(bytes: Vec<u8>) {
-    //     let page = Page::new_from_buffer(&mut ByteBuffer::from_bytes(&bytes));
-    //     match page.get_u64(0) {
-    //         4 => SetIntRecord::new(page),
-    //         5 => SetStringRecord::new(page),
-    //     }
-    // }
+pub trait LogRecord: Any {
+    fn op(&self) -> u64;
+
+    fn tx_number(&self) -> i32;
+
+    // The block an update record changed.
+    fn block(&self) -> Option<BlockId> {
+        None
+    }
+
+    // Only records that change a page have anything to undo.
+    fn undo(&mut self, _tx: &mut Transaction) -> DbResult<()> {
+        Ok(())
+    }
+
+    fn redo(&mut self, _tx: &mut Transaction) -> DbResult<()> {
+        Ok(())
+    }
+
+    // Undoes the change as a new, logged update; used by partial rollbacks.
+    fn compensate(&mut self, _tx: &mut Transaction) -> DbResult<()> {
+        Ok(())
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>>;
+}
+
+impl dyn LogRecord {
+    pub fn decode(bytes: &[u8]) -> DbResult<Box<dyn LogRecord>> {
+        let page = Page::new_from_buffer(&mut ByteBuffer::from_bytes(bytes));
+        Ok(match page.get_u64(0)? {
+            CHECKPOINT => Box::new(CheckpointRecord::new(page)?),
+            START => Box::new(StartRecord::new(page)?),
+            COMMIT => Box::new(CommitRecord::new(page)?),
+            ROLLBACK => Box::new(RollbackRecord::new(page)?),
+            SETINT => Box::new(SetIntRecord::new(page)?),
+            SETSTRING => Box::new(SetStringRecord::new(page)?),
+            NQCKPT => Box::new(NqCheckpointRecord::new(page)?),
+            SAVEPOINT => Box::new(SavepointRecord::new(page)?),
+            PREPARE => Box::new(PrepareRecord::new(page)?),
+            PAGEIMAGE => Box::new(PageImageRecord::new(page)?),
+            op => return Err(DbError::Corruption(format!("unknown log record type {op}"))),
+        })
+    }
+
+    pub fn downcast_ref<T: LogRecord>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref::<T>()
+    }
+}
+
+// Walks the log from the most recent record backwards, decoding each one.
+pub struct LogRecordIterator {
+    iter: LogIterator,
+}
+
+impl LogRecordIterator {
+    pub fn new(iter: LogIterator) -> Self {
+        LogRecordIterator { iter }
+    }
+
+    // The log segment of the record returned last.
+    pub fn segment(&self) -> u64 {
+        self.iter.segment()
+    }
+}
+
+impl Iterator for LogRecordIterator {
+    type Item = DbResult<Box<dyn LogRecord>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|bytes| bytes.and_then(|bytes| <dyn LogRecord>::decode(&bytes)))
+    }
 }
@@ -0,0 +1,71 @@
+use bytebuffer::ByteBuffer;
+
+use crate::{error::DbResult, file::page::Page, logging::log_mgr::LogMgr};
+
+use super::log_record::{LogRecord, NQCKPT};
+
+pub struct NqCheckpointRecord {
+    max_tx_num: i32,
+    tx_nums: Vec<i32>,
+}
+
+impl NqCheckpointRecord {
+    pub fn new(page: Page) -> DbResult<Self> {
+        let m_pos = 8;
+        let max_tx_num = page.get_i32(m_pos)?;
+        let c_pos = m_pos + 4;
+        let count = page.get_i32(c_pos)?;
+        let tx_nums = (0..count as usize)
+            .map(|i| page.get_i32(c_pos + 4 + 4 * i))
+            .collect::<DbResult<_>>()?;
+        Ok(NqCheckpointRecord {
+            max_tx_num,
+            tx_nums,
+        })
+    }
+
+    pub fn write_to_log(lm: &mut LogMgr, max_tx_num: i32, tx_nums: Vec<i32>) -> DbResult<i64> {
+        let rec = NqCheckpointRecord {
+            max_tx_num,
+            tx_nums,
+        };
+        lm.reset_page_images();
+        lm.append(rec.encode()?)
+    }
+
+    // The highest transaction number handed out when the checkpoint was taken.
+    pub fn max_tx_num(&self) -> i32 {
+        self.max_tx_num
+    }
+
+    // The transactions that were running when the checkpoint was taken.
+    pub fn tx_nums(&self) -> &Vec<i32> {
+        &self.tx_nums
+    }
+}
+
+impl LogRecord for NqCheckpointRecord {
+    fn op(&self) -> u64 {
+        NQCKPT
+    }
+
+    fn tx_number(&self) -> i32 {
+        // dummy value
+        -1
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>> {
+        let m_pos = 8;
+        let c_pos = m_pos + 4;
+        let mut rec = ByteBuffer::new();
+        rec.resize(c_pos + 4 + 4 * self.tx_nums.len());
+        let mut p = Page::new_from_buffer(&mut rec);
+        p.set_u64(0, NQCKPT)?;
+        p.set_i32(m_pos, self.max_tx_num)?;
+        p.set_i32(c_pos, self.tx_nums.len().try_into().unwrap())?;
+        for (i, tx_num) in self.tx_nums.iter().enumerate() {
+            p.set_i32(c_pos + 4 + 4 * i, *tx_num)?;
+        }
+        Ok(p.buffer.into_vec())
+    }
+}
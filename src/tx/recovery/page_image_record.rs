@@ -0,0 +1,111 @@
+use bytebuffer::ByteBuffer;
+
+use crate::{
+    error::DbResult,
+    file::{
+        block_id::BlockId,
+        page::{Page, LENGTH_SIZE},
+    },
+    logging::log_mgr::LogMgr,
+};
+
+use super::log_record::{LogRecord, PAGEIMAGE};
+
+// A slice of a block's contents as they were before the first change after a
+// checkpoint. A whole page doesn't fit in one log block, so an image is
+// logged as consecutive records that together cover the page.
+pub struct PageImageRecord {
+    tx_num: i32,
+    blk: BlockId,
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+impl PageImageRecord {
+    pub fn new(page: Page) -> DbResult<Self> {
+        let t_pos = 8;
+        let tx_num = page.get_i32(t_pos)?;
+        let f_pos = t_pos + 4;
+        let file_name = page.get_string(f_pos)?;
+        let b_pos = f_pos + Page::max_length(file_name.len());
+        let blk_num = page.get_u64(b_pos)?;
+        let blk = BlockId::new(file_name, blk_num);
+        let o_pos = b_pos + 8;
+        let offset = page.get_u64(o_pos)?;
+        let bytes = page.get_bytes(o_pos + 8)?;
+        Ok(PageImageRecord {
+            tx_num,
+            blk,
+            offset,
+            bytes,
+        })
+    }
+
+    // Returns the LSN of the last of the records.
+    pub fn write_to_log(
+        lm: &mut LogMgr,
+        tx_num: i32,
+        blk: &mut BlockId,
+        image: &[u8],
+    ) -> DbResult<i64> {
+        let chunk_len = lm.max_record_len() - Self::header_len(blk);
+        let mut lsn = -1;
+        for (i, chunk) in image.chunks(chunk_len).enumerate() {
+            let rec = PageImageRecord {
+                tx_num,
+                blk: blk.to_owned(),
+                offset: (i * chunk_len) as u64,
+                bytes: chunk.to_vec(),
+            };
+            lsn = lm.append(rec.encode()?)?;
+        }
+        Ok(lsn)
+    }
+
+    // Where the slice goes in the page.
+    pub fn offset(&self) -> usize {
+        self.offset as usize
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // Everything but the slice itself.
+    fn header_len(blk: &mut BlockId) -> usize {
+        8 + 4 + Page::max_length(blk.file_name().len()) + 8 + 8 + LENGTH_SIZE
+    }
+}
+
+impl LogRecord for PageImageRecord {
+    fn op(&self) -> u64 {
+        PAGEIMAGE
+    }
+
+    fn tx_number(&self) -> i32 {
+        self.tx_num
+    }
+
+    fn block(&self) -> Option<BlockId> {
+        Some(self.blk.clone())
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>> {
+        let mut blk = self.blk.to_owned();
+        let t_pos = 8;
+        let f_pos = t_pos + 4;
+        let b_pos = f_pos + Page::max_length(blk.file_name().len());
+        let o_pos = b_pos + 8;
+        let v_pos = o_pos + 8;
+        let mut rec = ByteBuffer::new();
+        rec.resize(v_pos + Page::max_length(self.bytes.len()));
+        let mut p = Page::new_from_buffer(&mut rec);
+        p.set_u64(0, PAGEIMAGE)?;
+        p.set_i32(t_pos, self.tx_num)?;
+        p.set_string(f_pos, blk.file_name().to_string())?;
+        p.set_u64(b_pos, blk.blk_num())?;
+        p.set_u64(o_pos, self.offset)?;
+        p.set_bytes(v_pos, self.bytes.clone())?;
+        Ok(p.buffer.into_vec())
+    }
+}
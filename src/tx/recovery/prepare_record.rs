@@ -0,0 +1,40 @@
+use bytebuffer::ByteBuffer;
+
+use crate::{error::DbResult, file::page::Page, logging::log_mgr::LogMgr};
+
+use super::log_record::{LogRecord, PREPARE};
+
+pub struct PrepareRecord {
+    tx_num: i32,
+}
+
+impl PrepareRecord {
+    pub fn new(page: Page) -> DbResult<Self> {
+        let t_pos = 8;
+        let tx_num = page.get_i32(t_pos)?;
+        Ok(PrepareRecord { tx_num })
+    }
+
+    pub fn write_to_log(lm: &mut LogMgr, tx_num: i32) -> DbResult<i64> {
+        lm.append(PrepareRecord { tx_num }.encode()?)
+    }
+}
+
+impl LogRecord for PrepareRecord {
+    fn op(&self) -> u64 {
+        PREPARE
+    }
+
+    fn tx_number(&self) -> i32 {
+        self.tx_num
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>> {
+        let mut rec = ByteBuffer::new();
+        rec.resize(8 + 4);
+        let mut p = Page::new_from_buffer(&mut rec);
+        p.set_u64(0, PREPARE)?;
+        p.set_i32(8, self.tx_num)?;
+        Ok(p.buffer.into_vec())
+    }
+}
@@ -1,23 +1,439 @@
+use bytebuffer::ByteBuffer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
 use crate::{
-    buffer::buffer_mgr::BufferMgr, file::file_mgr::FileMgr, logging::log_mgr::LogMgr,
+    buffer::buffer_mgr::BufferMgr,
+    error::{DbError, DbResult},
+    file::{block_id::BlockId, file_mgr::FileMgr, page::Page},
+    logging::log_mgr::LogMgr,
     tx::transaction::Transaction,
 };
 
+use super::{
+    checkpoint_record::CheckpointRecord,
+    commit_record::CommitRecord,
+    log_record::{
+        LogRecord, LogRecordIterator, CHECKPOINT, COMMIT, NQCKPT, PAGEIMAGE, PREPARE, ROLLBACK,
+        SAVEPOINT, START,
+    },
+    nq_checkpoint_record::NqCheckpointRecord,
+    page_image_record::PageImageRecord,
+    prepare_record::PrepareRecord,
+    rollback_record::RollbackRecord,
+    savepoint_record::SavepointRecord,
+    set_int_record::SetIntRecord,
+    set_string_record::SetStringRecord,
+    start_record::StartRecord,
+};
+
+// UndoOnly forces a transaction's buffers to disk at commit. UndoRedo only
+// forces the log and leaves the dirty pages in the pool; restart recovery
+// then redoes the committed changes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RecoveryMode {
+    UndoOnly,
+    UndoRedo,
+}
+
+// A transaction that had prepared but was neither committed nor rolled back
+// when the database went down. Recovery keeps its changes; the coordinator
+// decides its outcome once it has been restored with the locks on its blocks.
+pub struct InDoubtTx {
+    pub tx_num: i32,
+    pub start_segment: u64,
+    pub blocks: Vec<BlockId>,
+}
+
+#[derive(Clone)]
 pub struct RecoveryMgr {
-    tx: Box<Transaction>,
-    tx_num: usize,
-    lm: LogMgr,
-    bm: BufferMgr,
+    fm: Arc<FileMgr>,
+    lm: Arc<Mutex<LogMgr>>,
+    bm: Arc<BufferMgr>,
+    tx_num: i32,
+    start_segment: u64,
+    mode: RecoveryMode,
 }
 
 impl RecoveryMgr {
-    pub fn new(tx: Transaction, tx_num: usize, lm: LogMgr, bm: BufferMgr) -> Self {
-        // startrecord
-        RecoveryMgr {
-            tx: Box::new(tx),
+    pub fn new(
+        tx_num: i32,
+        fm: Arc<FileMgr>,
+        lm: Arc<Mutex<LogMgr>>,
+        bm: Arc<BufferMgr>,
+        mode: RecoveryMode,
+    ) -> DbResult<Self> {
+        let start_segment = {
+            let mut lm = lm.lock().unwrap();
+            StartRecord::write_to_log(&mut lm, tx_num)?;
+            lm.current_segment()
+        };
+        Ok(RecoveryMgr {
+            fm,
+            lm,
+            bm,
             tx_num,
+            start_segment,
+            mode,
+        })
+    }
+
+    // Picks up an in-doubt transaction after a restart; its START is already logged.
+    pub fn restore(
+        tx_num: i32,
+        fm: Arc<FileMgr>,
+        lm: Arc<Mutex<LogMgr>>,
+        bm: Arc<BufferMgr>,
+        mode: RecoveryMode,
+        start_segment: u64,
+    ) -> Self {
+        RecoveryMgr {
+            fm,
             lm,
             bm,
+            tx_num,
+            start_segment,
+            mode,
+        }
+    }
+
+    // The log segment holding this transaction's START record.
+    pub fn start_segment(&self) -> u64 {
+        self.start_segment
+    }
+
+    pub fn commit(&mut self) -> DbResult<()> {
+        if self.mode == RecoveryMode::UndoOnly {
+            self.bm.flush_all(self.tx_num)?;
+        }
+        let mut lm = self.lm.lock().unwrap();
+        let lsn = CommitRecord::write_to_log(&mut lm, self.tx_num)?;
+        lm.flush_with_lsn(lsn)
+    }
+
+    // Forces what commit would, so that either outcome survives a crash.
+    pub fn prepare(&mut self) -> DbResult<()> {
+        if self.mode == RecoveryMode::UndoOnly {
+            self.bm.flush_all(self.tx_num)?;
+        }
+        let mut lm = self.lm.lock().unwrap();
+        let lsn = PrepareRecord::write_to_log(&mut lm, self.tx_num)?;
+        lm.flush_with_lsn(lsn)
+    }
+
+    // The undo writes aren't logged, so they are forced to disk in either mode.
+    pub fn rollback(&mut self, tx: &mut Transaction) -> DbResult<()> {
+        self.do_rollback(tx)?;
+        self.bm.flush_all(self.tx_num)?;
+        let mut lm = self.lm.lock().unwrap();
+        let lsn = RollbackRecord::write_to_log(&mut lm, self.tx_num)?;
+        lm.flush_with_lsn(lsn)
+    }
+
+    pub fn savepoint(&mut self, name: &str) -> DbResult<()> {
+        let mut lm = self.lm.lock().unwrap();
+        SavepointRecord::write_to_log(&mut lm, self.tx_num, name.to_string())?;
+        Ok(())
+    }
+
+    // Undo this transaction's changes since its latest savepoint with the name.
+    // The undo writes are logged as ordinary updates, so a later rollback or
+    // recovery undoes them like any other, and the savepoint is logged again
+    // so that a later partial rollback stops before them.
+    pub fn rollback_to(&mut self, tx: &mut Transaction, name: &str) -> DbResult<()> {
+        let mut recs: Vec<Box<dyn LogRecord>> = vec![];
+        let iter = self.lm.lock().unwrap().iterator()?;
+        for rec in LogRecordIterator::new(iter) {
+            let rec = rec?;
+            if rec.tx_number() != self.tx_num {
+                continue;
+            }
+            if rec.op() == START {
+                break;
+            }
+            if rec.op() == SAVEPOINT {
+                let savepoint = rec.downcast_ref::<SavepointRecord>().unwrap();
+                if savepoint.name() == name {
+                    break;
+                }
+            }
+            recs.push(rec);
+        }
+        for rec in recs.iter_mut() {
+            rec.compensate(tx)?;
+        }
+        self.savepoint(name)
+    }
+
+    // In-doubt transactions are still running once recovery is done, so they
+    // are listed in a <NQCKPT> instead of a <CHECKPOINT> that would hide them.
+    pub fn recover(&mut self, tx: &mut Transaction) -> DbResult<Vec<InDoubtTx>> {
+        self.repair_torn_pages(tx)?;
+        let in_doubt = self.do_recover(tx)?;
+        self.bm.flush_all(self.tx_num)?;
+        let mut lm = self.lm.lock().unwrap();
+        // nothing else runs during recovery, so this is the highest number yet
+        let lsn = if in_doubt.is_empty() {
+            CheckpointRecord::write_to_log(&mut lm, self.tx_num)?
+        } else {
+            let tx_nums = in_doubt.iter().map(|t| t.tx_num).collect();
+            NqCheckpointRecord::write_to_log(&mut lm, self.tx_num, tx_nums)?
+        };
+        lm.flush_with_lsn(lsn)?;
+        Ok(in_doubt)
+    }
+
+    // The caller holds the exclusive latch on the page.
+    pub fn set_int(
+        &mut self,
+        page: &Page,
+        blk: &mut BlockId,
+        offset: u64,
+        new_val: i32,
+    ) -> DbResult<i64> {
+        let old_val = page.get_i32(offset.try_into().unwrap())?;
+        let mut lm = self.lm.lock().unwrap();
+        self.log_page_image(&mut lm, page, blk)?;
+        SetIntRecord::write_to_log(&mut lm, self.tx_num, blk, offset, old_val, new_val)
+    }
+
+    pub fn set_string(
+        &mut self,
+        page: &Page,
+        blk: &mut BlockId,
+        offset: u64,
+        new_val: String,
+    ) -> DbResult<i64> {
+        let old_val = page.get_string(offset.try_into().unwrap())?;
+        let mut lm = self.lm.lock().unwrap();
+        self.log_page_image(&mut lm, page, blk)?;
+        SetStringRecord::write_to_log(&mut lm, self.tx_num, blk, offset, old_val, new_val)
+    }
+
+    // Logs the page as it is before its first change since the latest checkpoint.
+    fn log_page_image(&self, lm: &mut LogMgr, page: &Page, blk: &mut BlockId) -> DbResult<()> {
+        if lm.needs_page_image(blk) {
+            let mut image = page.buffer.as_bytes().to_vec();
+            image.resize(self.fm.block_size() as usize, 0);
+            PageImageRecord::write_to_log(lm, self.tx_num, blk, &image)?;
+        }
+        Ok(())
+    }
+
+    // A block that fails its checksum was torn by a crash in the middle of
+    // its write. Every block changed since the latest checkpoint has an image
+    // in the log from before that change, so such a block is rebuilt from
+    // its image and replaying what was logged after it. A rollback's undo
+    // wasn't logged, so it is done again at its ROLLBACK, reaching back past
+    // the checkpoint for changes the image already holds. The usual undo and
+    // redo then run on the rebuilt blocks.
+    fn repair_torn_pages(&mut self, tx: &mut Transaction) -> DbResult<()> {
+        // since the checkpoint, newest first
+        let mut recs = vec![];
+        // the rolled-back transactions and whether their START turned up
+        let mut rolled_back: HashMap<i32, bool> = HashMap::new();
+        // what those that started before the checkpoint logged before it
+        let mut older = vec![];
+        let mut past_checkpoint = false;
+        let iter = self.lm.lock().unwrap().iterator()?;
+        for rec in LogRecordIterator::new(iter) {
+            let rec = rec?;
+            let tx_num = rec.tx_number();
+            if !past_checkpoint {
+                match rec.op() {
+                    CHECKPOINT | NQCKPT => past_checkpoint = true,
+                    ROLLBACK => {
+                        rolled_back.insert(tx_num, false);
+                    }
+                    START => {
+                        if let Some(started) = rolled_back.get_mut(&tx_num) {
+                            *started = true;
+                        }
+                    }
+                    _ => {}
+                }
+                if !past_checkpoint {
+                    recs.push(rec);
+                }
+                continue;
+            }
+            // nothing runs across a quiescent checkpoint
+            if rec.op() == CHECKPOINT || rolled_back.values().all(|started| *started) {
+                break;
+            }
+            if let Some(started) = rolled_back.get_mut(&tx_num) {
+                if rec.op() == START {
+                    *started = true;
+                } else {
+                    older.push(rec);
+                }
+            }
+        }
+        let block_size = self.fm.block_size() as usize;
+        // the images and how many of their bytes were logged
+        let mut images: HashMap<BlockId, (Vec<u8>, usize)> = HashMap::new();
+        for rec in recs.iter() {
+            if let Some(image) = rec.downcast_ref::<PageImageRecord>() {
+                let (bytes, len) = images
+                    .entry(rec.block().unwrap())
+                    .or_insert_with(|| (vec![0; block_size], 0));
+                let slice = image.bytes();
+                bytes[image.offset()..image.offset() + slice.len()].copy_from_slice(slice);
+                *len += slice.len();
+            }
+        }
+        let mut torn = vec![];
+        let mut scratch = Page::new(block_size as u64);
+        for (mut blk, (bytes, len)) in images {
+            // an image cut short by the crash belongs to a change that wasn't logged
+            if len < block_size {
+                continue;
+            }
+            match self.fm.read(&mut blk, &mut scratch) {
+                Err(DbError::Corruption(_)) => {
+                    let mut page = Page::new_from_buffer(&mut ByteBuffer::from_vec(bytes));
+                    self.fm.write(&mut blk, &mut page)?;
+                    torn.push(blk);
+                }
+                res => res?,
+            }
+        }
+        let repaired = |rec: &dyn LogRecord| rec.block().is_some_and(|blk| torn.contains(&blk));
+        for i in (0..recs.len()).rev() {
+            match recs[i].op() {
+                PAGEIMAGE => {}
+                ROLLBACK => {
+                    let tx_num = recs[i].tx_number();
+                    for rec in recs[i + 1..].iter_mut().chain(older.iter_mut()) {
+                        if rec.tx_number() == tx_num && repaired(rec.as_ref()) {
+                            rec.undo(tx)?;
+                        }
+                    }
+                }
+                _ => {
+                    if repaired(recs[i].as_ref()) {
+                        recs[i].redo(tx)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Undo the records of this transaction, walking back until its START record.
+    fn do_rollback(&mut self, tx: &mut Transaction) -> DbResult<()> {
+        let iter = self.lm.lock().unwrap().iterator()?;
+        for rec in LogRecordIterator::new(iter) {
+            let mut rec = rec?;
+            if rec.tx_number() == self.tx_num {
+                if rec.op() == START {
+                    break;
+                }
+                rec.undo(tx)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Walk back undoing the records of every transaction that neither committed
+    // nor rolled back, until the most recent <CHECKPOINT>. Past a <NQCKPT> only the
+    // transactions it lists are undone, until their START records, unless their
+    // COMMIT or ROLLBACK turns up first.
+    // Then redo the committed changes logged after the most recent checkpoint,
+    // oldest first; every page was flushed when that checkpoint was taken.
+    // Transactions that prepared but never finished are in doubt: their changes
+    // are redone like committed ones and their blocks collected down to their START.
+    fn do_recover(&mut self, tx: &mut Transaction) -> DbResult<Vec<InDoubtTx>> {
+        let mut committed_txs = vec![];
+        let mut finished_txs = vec![];
+        let mut in_doubt: Vec<InDoubtTx> = vec![];
+        let mut redo_recs = vec![];
+        let mut pending_txs: Option<Vec<i32>> = None;
+        let iter = self.lm.lock().unwrap().iterator()?;
+        let mut recs = LogRecordIterator::new(iter);
+        while let Some(rec) = recs.next() {
+            let mut rec = rec?;
+            let tx_num = rec.tx_number();
+            match rec.op() {
+                CHECKPOINT => break,
+                NQCKPT => {
+                    let nq = rec.downcast_ref::<NqCheckpointRecord>().unwrap();
+                    let pending: Vec<i32> = nq
+                        .tx_nums()
+                        .iter()
+                        .copied()
+                        .filter(|tx_num| !finished_txs.contains(tx_num))
+                        .collect();
+                    if pending.is_empty() {
+                        break;
+                    }
+                    pending_txs = Some(pending);
+                }
+                COMMIT | ROLLBACK => {
+                    if rec.op() == COMMIT {
+                        committed_txs.push(tx_num);
+                    }
+                    finished_txs.push(tx_num);
+                    // a listed transaction that finished before the <NQCKPT>
+                    // was written; its changes were flushed with the checkpoint
+                    if let Some(pending) = pending_txs.as_mut() {
+                        pending.retain(|t| *t != tx_num);
+                        if pending.is_empty() {
+                            break;
+                        }
+                    }
+                }
+                PREPARE => {
+                    if !finished_txs.contains(&tx_num) {
+                        in_doubt.push(InDoubtTx {
+                            tx_num,
+                            start_segment: 0,
+                            blocks: vec![],
+                        });
+                    }
+                }
+                op => {
+                    let doubtful = match in_doubt.iter_mut().find(|t| t.tx_num == tx_num) {
+                        Some(t) => {
+                            if op == START {
+                                t.start_segment = recs.segment();
+                            } else if let Some(blk) = rec.block() {
+                                if !t.blocks.contains(&blk) {
+                                    t.blocks.push(blk);
+                                }
+                            }
+                            true
+                        }
+                        None => false,
+                    };
+                    match pending_txs.as_mut() {
+                        None => {
+                            if doubtful || committed_txs.contains(&tx_num) {
+                                redo_recs.push(rec);
+                            } else if !finished_txs.contains(&tx_num) {
+                                rec.undo(tx)?;
+                            }
+                        }
+                        Some(pending) => {
+                            if !pending.contains(&tx_num) {
+                                continue;
+                            }
+                            if op == START {
+                                pending.retain(|t| *t != tx_num);
+                                if pending.is_empty() {
+                                    break;
+                                }
+                            } else if !doubtful {
+                                rec.undo(tx)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for rec in redo_recs.iter_mut().rev() {
+            rec.redo(tx)?;
         }
+        Ok(in_doubt)
     }
 }
@@ -0,0 +1,50 @@
+use bytebuffer::ByteBuffer;
+
+use crate::{error::DbResult, file::page::Page, logging::log_mgr::LogMgr};
+
+use super::log_record::{LogRecord, SAVEPOINT};
+
+pub struct SavepointRecord {
+    tx_num: i32,
+    name: String,
+}
+
+impl SavepointRecord {
+    pub fn new(page: Page) -> DbResult<Self> {
+        let t_pos = 8;
+        let tx_num = page.get_i32(t_pos)?;
+        let n_pos = t_pos + 4;
+        let name = page.get_string(n_pos)?;
+        Ok(SavepointRecord { tx_num, name })
+    }
+
+    pub fn write_to_log(lm: &mut LogMgr, tx_num: i32, name: String) -> DbResult<i64> {
+        lm.append(SavepointRecord { tx_num, name }.encode()?)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl LogRecord for SavepointRecord {
+    fn op(&self) -> u64 {
+        SAVEPOINT
+    }
+
+    fn tx_number(&self) -> i32 {
+        self.tx_num
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>> {
+        let t_pos = 8;
+        let n_pos = t_pos + 4;
+        let mut rec = ByteBuffer::new();
+        rec.resize(n_pos + Page::max_length(self.name.len()));
+        let mut p = Page::new_from_buffer(&mut rec);
+        p.set_u64(0, SAVEPOINT)?;
+        p.set_i32(t_pos, self.tx_num)?;
+        p.set_string(n_pos, self.name.to_string())?;
+        Ok(p.buffer.into_vec())
+    }
+}
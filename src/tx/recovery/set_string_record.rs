@@ -2,80 +2,114 @@ use bytebuffer::ByteBuffer;
 
 use crate::tx::transaction::Transaction;
 use crate::{
+    error::DbResult,
     file::{block_id::BlockId, page::Page},
     logging::log_mgr::LogMgr,
 };
 
+use super::log_record::{LogRecord, SETSTRING};
+
 pub struct SetStringRecord {
     tx_num: i32,
     offset: u64,
-    val: String,
+    old_val: String,
+    new_val: String,
     blk: BlockId,
 }
 
 impl SetStringRecord {
-    pub fn new(mut page: Page) -> Self {
-        let t_pos = 4;
-        let tx_num = page.get_i32(t_pos);
+    pub fn new(page: Page) -> DbResult<Self> {
+        let t_pos = 8;
+        let tx_num = page.get_i32(t_pos)?;
         let f_pos = t_pos + 4;
-        let file_name = page.get_string(f_pos);
-        let b_pos = f_pos + page.max_length(file_name.len());
-        let blk_num = page.get_u64(b_pos);
+        let file_name = page.get_string(f_pos)?;
+        let b_pos = f_pos + Page::max_length(file_name.len());
+        let blk_num = page.get_u64(b_pos)?;
         let blk = BlockId::new(file_name, blk_num);
-        let o_pos = b_pos + 4;
-        let offset = page.get_u64(o_pos);
-        let v_pos = o_pos + 4;
-        let val = page.get_string(v_pos);
-        SetStringRecord {
+        let o_pos = b_pos + 8;
+        let offset = page.get_u64(o_pos)?;
+        let v_pos = o_pos + 8;
+        let old_val = page.get_string(v_pos)?;
+        let n_pos = v_pos + Page::max_length(old_val.len());
+        let new_val = page.get_string(n_pos)?;
+        Ok(SetStringRecord {
             tx_num,
             offset,
-            val,
+            old_val,
+            new_val,
             blk,
-        }
+        })
+    }
+
+    pub fn write_to_log(
+        lm: &mut LogMgr,
+        tx_num: i32,
+        blk: &mut BlockId,
+        offset: u64,
+        old_val: String,
+        new_val: String,
+    ) -> DbResult<i64> {
+        let rec = SetStringRecord {
+            tx_num,
+            offset,
+            old_val,
+            new_val,
+            blk: blk.to_owned(),
+        };
+        lm.append(rec.encode()?)
     }
+}
 
-    pub fn op(&mut self) -> u64 {
-        // SETSTRING = 5
-        5
+impl LogRecord for SetStringRecord {
+    fn op(&self) -> u64 {
+        SETSTRING
     }
 
-    pub fn tx_num(&mut self) -> i32 {
+    fn tx_number(&self) -> i32 {
         self.tx_num
     }
 
-    pub fn undo(&mut self, tx: &mut Transaction) {
-        tx.pin(&mut self.blk);
-        tx.set_string(&mut self.blk, self.offset, self.val.to_string(), false); // don't log the undo!
-        tx.unpin(&mut self.blk);
+    fn block(&self) -> Option<BlockId> {
+        Some(self.blk.clone())
     }
 
-    pub fn write_to_log(
-        lm: &mut LogMgr,
-        tx_num: i32,
-        blk: &mut BlockId,
-        offset: u64,
-        val: String,
-    ) -> i64 {
-        let t_pos = 4;
+    fn undo(&mut self, tx: &mut Transaction) -> DbResult<()> {
+        tx.pin(&mut self.blk)?;
+        tx.set_string(&mut self.blk, self.offset, self.old_val.to_string(), false)?; // don't log the undo!
+        tx.unpin(&mut self.blk)
+    }
+
+    fn compensate(&mut self, tx: &mut Transaction) -> DbResult<()> {
+        tx.pin(&mut self.blk)?;
+        tx.set_string(&mut self.blk, self.offset, self.old_val.to_string(), true)?;
+        tx.unpin(&mut self.blk)
+    }
+
+    fn redo(&mut self, tx: &mut Transaction) -> DbResult<()> {
+        tx.pin(&mut self.blk)?;
+        tx.set_string(&mut self.blk, self.offset, self.new_val.to_string(), false)?;
+        tx.unpin(&mut self.blk)
+    }
+
+    fn encode(&self) -> DbResult<Vec<u8>> {
+        let mut blk = self.blk.to_owned();
+        let t_pos = 8;
         let f_pos = t_pos + 4;
-        let b_pos = f_pos + Self::max_length(blk.file_name().len());
-        let o_pos = b_pos + 4;
-        let v_pos = o_pos + 4;
-        let rec_len = v_pos + Self::max_length(val.len());
+        let b_pos = f_pos + Page::max_length(blk.file_name().len());
+        let o_pos = b_pos + 8;
+        let v_pos = o_pos + 8;
+        let n_pos = v_pos + Page::max_length(self.old_val.len());
+        let rec_len = n_pos + Page::max_length(self.new_val.len());
         let mut rec = ByteBuffer::new();
         rec.resize(rec_len);
         let mut p = Page::new_from_buffer(&mut rec);
-        // SETINT = 5
-        p.set_u64(0, 5);
-        p.set_i32(t_pos, tx_num);
-        p.set_string(f_pos, blk.file_name().to_string());
-        p.set_u64(b_pos, blk.blk_num());
-        p.set_u64(o_pos, offset);
-        p.set_string(v_pos, val);
-        lm.append(rec.into_bytes())
-    }
-
-    fn max_length(str_len: usize) -> usize {
-        4 + str_len
+        p.set_u64(0, SETSTRING)?;
+        p.set_i32(t_pos, self.tx_num)?;
+        p.set_string(f_pos, blk.file_name().to_string())?;
+        p.set_u64(b_pos, blk.blk_num())?;
+        p.set_u64(o_pos, self.offset)?;
+        p.set_string(v_pos, self.old_val.to_string())?;
+        p.set_string(n_pos, self.new_val.to_string())?;
+        Ok(p.buffer.into_vec())
     }
 }
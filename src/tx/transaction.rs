@@ -1,193 +1,291 @@
-use crate::tx::recovery::commit_record::CommitRecord;
-use crate::tx::recovery::set_int_record::SetIntRecord;
-use crate::tx::recovery::set_string_record::SetStringRecord;
+use crate::tx::recovery::recovery_mgr::{InDoubtTx, RecoveryMgr};
 use crate::{
-    buffer::buffer_mgr::BufferMgr,
-    file::{block_id::BlockId, file_mgr::FileMgr, page::Page},
-    logging::log_mgr::LogMgr,
-    tx::concurrency::concurrency_mgr::ConcurrencyMgr,
+    buffer::{buffer::Buffer, buffer_mgr::BufferMgr},
+    error::{DbError, DbResult},
+    file::{
+        block_id::BlockId,
+        file_mgr::FileMgr,
+        page::{Page, I32_SIZE},
+    },
+    tx::concurrency::{
+        concurrency_mgr::{ConcurrencyMgr, IsolationLevel},
+        lock_table::LockTable,
+        version_store::VersionStore,
+    },
 };
-use bytebuffer::ByteBuffer;
+use std::sync::{Arc, Mutex};
 
 use super::buffer_list::BufferList;
+use super::tx_registry::TxRegistry;
 
+// The dummy block number locked for the end of a file.
+pub const END_OF_FILE: u64 = u64::MAX;
+
+// Cloning a transaction gives another handle to the same transaction,
+// so record pages and scans share its pins and locks.
 #[derive(Clone)]
 pub struct Transaction {
-    fm: FileMgr,
-    lm: LogMgr,
-    bm: BufferMgr,
+    fm: Arc<FileMgr>,
+    bm: Arc<BufferMgr>,
+    tx_registry: Arc<TxRegistry>,
     tx_num: i32,
-    next_tx_num: usize,
-    end_of_file: i32,
-    // recovery_mgr: RecoveryMgr,
-    concurrency_mgr: ConcurrencyMgr,
-    my_buffers: BufferList,
+    recovery_mgr: RecoveryMgr,
+    concurrency_mgr: Arc<Mutex<ConcurrencyMgr>>,
+    // only set in MVCC mode
+    version_store: Option<Arc<VersionStore>>,
+    my_buffers: Arc<Mutex<BufferList>>,
+    // the live savepoints, oldest first
+    savepoints: Arc<Mutex<Vec<String>>>,
+    prepared: Arc<Mutex<bool>>,
+    // set once the transaction has committed or rolled back
+    finished: Arc<Mutex<bool>>,
+    // set while logged changes are undone or redone
+    undoing: Arc<Mutex<bool>>,
 }
 
 impl Transaction {
-    pub fn new(fm: &mut FileMgr, lm: &mut LogMgr, bm: &mut BufferMgr) -> Self {
-        let tx_num = 1;
+    // The transaction must already be registered with tx_registry.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        tx_num: i32,
+        fm: Arc<FileMgr>,
+        bm: Arc<BufferMgr>,
+        tx_registry: Arc<TxRegistry>,
+        recovery_mgr: RecoveryMgr,
+        lock_tbl: Arc<LockTable>,
+        version_store: Option<Arc<VersionStore>>,
+        isolation: IsolationLevel,
+    ) -> Self {
+        if let Some(vs) = &version_store {
+            vs.begin(tx_num);
+        }
         Transaction {
-            fm: fm.to_owned(),
-            lm: lm.to_owned(),
-            bm: bm.to_owned(),
+            fm,
+            bm: bm.clone(),
+            tx_registry,
             tx_num,
-            next_tx_num: tx_num.try_into().unwrap(),
-            end_of_file: -1,
-            concurrency_mgr: ConcurrencyMgr::new(),
-            my_buffers: BufferList::new(bm),
+            recovery_mgr,
+            concurrency_mgr: Arc::new(Mutex::new(ConcurrencyMgr::new(tx_num, isolation, lock_tbl))),
+            version_store,
+            my_buffers: Arc::new(Mutex::new(BufferList::new(bm))),
+            savepoints: Arc::new(Mutex::new(vec![])),
+            prepared: Arc::new(Mutex::new(false)),
+            finished: Arc::new(Mutex::new(false)),
+            undoing: Arc::new(Mutex::new(false)),
         }
     }
 
-    pub fn commit(&mut self) {
-        self.bm.flush_all(self.tx_num);
-        let lsn = CommitRecord::write_to_log(&mut self.lm, self.tx_num);
-        self.lm.flush_with_lsn(lsn);
+    // Under MVCC a write-write conflict rolls the transaction back and
+    // returns WriteConflict, as does a wound under WoundWait with LockAbort.
+    // A finished transaction, including one that was
+    // rolled back after an error, can't commit.
+    pub fn commit(&mut self) -> DbResult<()> {
+        self.check_not_finished()?;
+        self.validate()?;
+        self.recovery_mgr.commit()?;
+        if let Some(vs) = &self.version_store {
+            // before the X locks go
+            vs.commit(self.tx_num);
+        }
         println!("transaction {} commited", self.tx_num);
-        self.concurrency_mgr.release();
-        self.my_buffers.unpin_all();
-    }
-
-    pub fn rollback(&mut self) {
-        let mut iter = self.lm.iterator();
-        while iter.has_next() {
-            let bytes = iter.next();
-            let mut page = Page::new_from_buffer(&mut ByteBuffer::from_bytes(&bytes));
-            let rec_int = match page.get_u64(0) {
-                4 => Some(SetIntRecord::new(page.clone())),
-                _ => None,
-            };
-            let rec_string = match page.get_u64(0) {
-                5 => Some(SetStringRecord::new(page.clone())),
-                _ => None,
-            };
-            match rec_int {
-                Some(mut rec) => {
-                    if rec.tx_num() == self.tx_num {
-                        rec.undo(self);
-                    }
-                }
-                None => {}
-            };
-            match rec_string {
-                Some(mut rec) => {
-                    if rec.tx_num() == self.tx_num {
-                        rec.undo(self);
-                    }
-                }
-                None => {}
-            };
+        *self.finished.lock().unwrap() = true;
+        self.concurrency_mgr.lock().unwrap().release();
+        self.my_buffers.lock().unwrap().unpin_all();
+        self.tx_registry.finish(self.tx_num);
+        Ok(())
+    }
+
+    // Rolling back a finished transaction does nothing, so a caller can roll
+    // back after an error whether or not it was rolled back already.
+    pub fn rollback(&mut self) -> DbResult<()> {
+        if self.is_finished() {
+            return Ok(());
+        }
+        let mut recovery_mgr = self.recovery_mgr.clone();
+        self.undoing(|tx| recovery_mgr.rollback(tx))?;
+        if let Some(vs) = &self.version_store {
+            vs.abort(self.tx_num);
         }
         println!("transaction {} rolled back", self.tx_num);
-        self.concurrency_mgr.release();
-        self.my_buffers.unpin_all();
+        *self.finished.lock().unwrap() = true;
+        self.concurrency_mgr.lock().unwrap().release();
+        self.my_buffers.lock().unwrap().unpin_all();
+        self.tx_registry.finish(self.tx_num);
+        Ok(())
     }
 
-    pub fn pin(&mut self, blk: &mut BlockId) {
-        self.my_buffers.pin(blk)
+    // First phase of a two-phase commit: the transaction can no longer be
+    // refused and keeps its locks until commit_prepared or rollback_prepared.
+    // Changes asked of it after this fail with AlreadyPrepared.
+    pub fn prepare(&mut self) -> DbResult<()> {
+        self.check_not_finished()?;
+        self.validate()?;
+        self.recovery_mgr.prepare()?;
+        *self.prepared.lock().unwrap() = true;
+        Ok(())
     }
 
-    pub fn unpin(&mut self, blk: &mut BlockId) {
-        self.my_buffers.unpin(blk)
+    pub fn commit_prepared(&mut self) -> DbResult<()> {
+        self.check_prepared()?;
+        self.commit()
     }
 
-    pub fn get_int(&mut self, blk: &mut BlockId, offset: u64) -> u64 {
-        self.concurrency_mgr.s_lock(blk);
-        let mut buffer = self.my_buffers.get_buffer(blk).unwrap().to_owned();
-        // println!("{:?}", buffer.contents());
-        buffer.contents().get_u64(offset.try_into().unwrap())
+    pub fn rollback_prepared(&mut self) -> DbResult<()> {
+        self.check_prepared()?;
+        self.rollback()
     }
 
-    pub fn get_int_u32(&mut self, blk: &mut BlockId, offset: u64) -> u32 {
-        self.concurrency_mgr.s_lock(blk);
-        let mut buffer = self.my_buffers.get_buffer(blk).unwrap().to_owned();
-        buffer.contents().get_u32(offset.try_into().unwrap())
+    pub fn is_prepared(&mut self) -> bool {
+        *self.prepared.lock().unwrap()
     }
 
-    pub fn get_string(&mut self, blk: &mut BlockId, offset: u64) -> String {
-        self.concurrency_mgr.s_lock(blk);
-        let mut buffer = self.my_buffers.get_buffer(blk).unwrap().to_owned();
-        buffer.contents().get_string(offset.try_into().unwrap())
+    pub fn is_finished(&mut self) -> bool {
+        *self.finished.lock().unwrap()
     }
 
-    pub fn set_int(&mut self, blk: &mut BlockId, offset: u64, val: u64, ok_to_log: bool) {
-        self.concurrency_mgr.x_lock(blk);
-        let mut buffer = self.my_buffers.get_buffer(blk).unwrap().to_owned();
-        let mut lsn = -1;
-        if ok_to_log {
-            let old_val = buffer.contents().get_u64(offset.try_into().unwrap());
-            let mut blk = buffer.block().unwrap();
-            lsn = SetIntRecord::write_to_log(&mut self.lm, self.tx_num, &mut blk, offset, old_val)
-        }
-        let mut page = buffer.contents();
-        page.set_u64(offset.try_into().unwrap(), val);
-        buffer.set_modified(self.tx_num.try_into().unwrap(), lsn.try_into().unwrap());
-        // need to set contents directly
-        buffer.set_contents(page);
-        // update my_buffers
-        self.my_buffers.set_buffer(blk.to_owned(), buffer.clone());
-        // update buffer_pool
-        // TODO: set index correctly
-        self.bm.buffer_pool[0] = buffer;
-    }
-
-    pub fn set_int_u32(&mut self, blk: &mut BlockId, offset: u64, val: u32, ok_to_log: bool) {
-        self.concurrency_mgr.x_lock(blk);
-        let mut buffer = self.my_buffers.get_buffer(blk).unwrap().to_owned();
+    // Puts an in-doubt transaction back into the prepared state after a
+    // restart, holding the locks on the blocks it changed.
+    pub fn restore_prepared(&mut self, blocks: Vec<BlockId>) -> DbResult<()> {
+        for mut blk in blocks {
+            // a refusal must not roll back what the coordinator still has to decide
+            self.concurrency_mgr.lock().unwrap().x_lock(&mut blk)?;
+        }
+        *self.prepared.lock().unwrap() = true;
+        Ok(())
+    }
+
+    // Reusing a name moves the savepoint.
+    pub fn savepoint(&mut self, name: &str) -> DbResult<()> {
+        self.check_not_finished()?;
+        let mut savepoints = self.savepoints.lock().unwrap();
+        savepoints.retain(|savepoint| savepoint != name);
+        savepoints.push(name.to_string());
+        self.recovery_mgr.savepoint(name)
+    }
+
+    // Undoes the changes made since the savepoint, which stays in place;
+    // the savepoints taken after it are dropped. Locks are kept.
+    pub fn rollback_to(&mut self, name: &str) -> DbResult<()> {
+        self.check_not_finished()?;
+        self.truncate_savepoints(name, true)?;
+        let mut recovery_mgr = self.recovery_mgr.clone();
+        self.undoing(|tx| recovery_mgr.rollback_to(tx, name))?;
+        Ok(())
+    }
+
+    // Drops the savepoint along with the ones taken after it.
+    pub fn release(&mut self, name: &str) -> DbResult<()> {
+        self.truncate_savepoints(name, false)
+    }
+
+    pub fn recover(&mut self) -> DbResult<Vec<InDoubtTx>> {
+        self.bm.flush_all(self.tx_num)?;
+        let mut recovery_mgr = self.recovery_mgr.clone();
+        self.undoing(|tx| recovery_mgr.recover(tx))
+    }
+
+    // A pin that can't be served rolls the transaction back.
+    pub fn pin(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        self.check_not_finished()?;
+        let res = self.my_buffers.lock().unwrap().pin(blk);
+        self.abort_on(res)
+    }
+
+    pub fn unpin(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        self.my_buffers.lock().unwrap().unpin(blk)
+    }
+
+    pub fn get_int(&mut self, blk: &mut BlockId, offset: u64) -> DbResult<i32> {
+        self.check_not_finished()?;
+        self.read(blk, |page| page.get_i32(offset.try_into().unwrap()))
+    }
+
+    pub fn get_string(&mut self, blk: &mut BlockId, offset: u64) -> DbResult<String> {
+        self.check_not_finished()?;
+        self.read(blk, |page| page.get_string(offset.try_into().unwrap()))
+    }
+
+    pub fn set_int(
+        &mut self,
+        blk: &mut BlockId,
+        offset: u64,
+        val: i32,
+        ok_to_log: bool,
+    ) -> DbResult<()> {
+        self.check_not_finished()?;
+        self.check_not_prepared()?;
+        self.x_lock(blk)?;
+        let buffer = self.pinned_buffer(blk)?;
+        if let Some(vs) = &self.version_store {
+            vs.before_write(self.tx_num, blk, &buffer);
+        }
+        let _updating = self.tx_registry.updating();
+        // the frame is only held to reach the page, not while the change is logged
+        let (latch, modification) = {
+            let buffer = buffer.lock().unwrap();
+            (buffer.latch(), buffer.modification())
+        };
+        let mut page = latch.write().unwrap();
+        page.check_fits(offset.try_into().unwrap(), I32_SIZE)?;
         let mut lsn = -1;
         if ok_to_log {
-            let old_val = buffer.contents().get_u64(offset.try_into().unwrap());
-            let mut blk = buffer.block().unwrap();
-            lsn = SetIntRecord::write_to_log(&mut self.lm, self.tx_num, &mut blk, offset, old_val)
-        }
-        let mut page = buffer.contents();
-        page.set_u32(offset.try_into().unwrap(), val);
-        buffer.set_modified(self.tx_num.try_into().unwrap(), lsn.try_into().unwrap());
-        // need to set contents directly
-        buffer.set_contents(page);
-        // update my_buffers
-        self.my_buffers.set_buffer(blk.to_owned(), buffer.clone());
-        // update buffer_pool
-        // TODO: set index correctly
-        self.bm.buffer_pool[0] = buffer;
-    }
-
-    pub fn set_string(&mut self, blk: &mut BlockId, offset: u64, val: String, ok_to_log: bool) {
-        self.concurrency_mgr.x_lock(blk);
-        let mut buffer = self.my_buffers.get_buffer(blk).unwrap().to_owned();
+            lsn = self.recovery_mgr.set_int(&page, blk, offset, val)?
+        }
+        page.set_i32(offset.try_into().unwrap(), val)?;
+        modification.set(self.tx_num, lsn);
+        Ok(())
+    }
+
+    pub fn set_string(
+        &mut self,
+        blk: &mut BlockId,
+        offset: u64,
+        val: String,
+        ok_to_log: bool,
+    ) -> DbResult<()> {
+        self.check_not_finished()?;
+        self.check_not_prepared()?;
+        self.x_lock(blk)?;
+        let buffer = self.pinned_buffer(blk)?;
+        if let Some(vs) = &self.version_store {
+            vs.before_write(self.tx_num, blk, &buffer);
+        }
+        let _updating = self.tx_registry.updating();
+        let (latch, modification) = {
+            let buffer = buffer.lock().unwrap();
+            (buffer.latch(), buffer.modification())
+        };
+        let mut page = latch.write().unwrap();
+        // refused before it is logged, or undoing the record would fail too
+        page.check_fits(offset.try_into().unwrap(), Page::max_length(val.len()))?;
         let mut lsn = -1;
         if ok_to_log {
-            let old_val = buffer.contents().get_string(offset.try_into().unwrap());
-            let blk = buffer.block();
-            lsn = SetStringRecord::write_to_log(
-                &mut self.lm,
-                self.tx_num,
-                &mut blk.unwrap(),
-                offset,
-                old_val,
-            )
-        }
-        let mut page = buffer.contents();
-        page.set_string(offset.try_into().unwrap(), val);
-        buffer.set_modified(self.tx_num.try_into().unwrap(), lsn.try_into().unwrap());
-        // need to set contents directly
-        buffer.set_contents(page);
-        // update my_buffers
-        self.my_buffers.set_buffer(blk.to_owned(), buffer.clone());
-        // update buffer_pool
-        // TODO: set index correctly
-        self.bm.buffer_pool[0] = buffer;
-    }
-
-    pub fn size(&mut self, file_name: &str) -> u64 {
-        let mut dummy_blk = BlockId::new(file_name.to_string(), 0);
-        self.concurrency_mgr.s_lock(&mut dummy_blk);
+            lsn = self
+                .recovery_mgr
+                .set_string(&page, blk, offset, val.clone())?
+        }
+        page.set_string(offset.try_into().unwrap(), val)?;
+        modification.set(self.tx_num, lsn);
+        Ok(())
+    }
+
+    // Only serializable transactions lock the end of the file, so that no
+    // other transaction can append to it until they end.
+    pub fn size(&mut self, file_name: &str) -> DbResult<u64> {
+        self.check_not_finished()?;
+        let serializable = self.isolation() == IsolationLevel::Serializable;
+        if serializable && self.version_store.is_none() {
+            let mut dummy_blk = BlockId::new(file_name.to_string(), END_OF_FILE);
+            self.s_lock(&mut dummy_blk)?;
+        }
         self.fm.length(file_name.to_string())
     }
 
-    pub fn append(&mut self, file_name: &str) -> BlockId {
-        let mut dummy_blk = BlockId::new(file_name.to_string(), 0);
-        self.concurrency_mgr.x_lock(&mut dummy_blk);
+    pub fn append(&mut self, file_name: &str) -> DbResult<BlockId> {
+        self.check_not_finished()?;
+        self.check_not_prepared()?;
+        let mut dummy_blk = BlockId::new(file_name.to_string(), END_OF_FILE);
+        self.x_lock(&mut dummy_blk)?;
         self.fm.append(&mut file_name.to_string())
     }
 
@@ -195,8 +293,122 @@ impl Transaction {
         self.fm.block_size
     }
 
-    fn next_tx_num(mut self) -> usize {
-        self.next_tx_num += 1;
-        self.next_tx_num
+    pub fn tx_num(&mut self) -> i32 {
+        self.tx_num
+    }
+
+    pub fn isolation(&mut self) -> IsolationLevel {
+        self.concurrency_mgr.lock().unwrap().isolation()
+    }
+
+    // Marks the end of a statement, e.g. when a scan is closed.
+    pub fn end_statement(&mut self) {
+        self.concurrency_mgr.lock().unwrap().end_statement()
+    }
+
+    // A transaction wounded by an older one is rolled back instead of
+    // committing, unless it has prepared and can no longer be refused.
+    fn validate(&mut self) -> DbResult<()> {
+        let res = match &self.version_store {
+            Some(vs) => vs.validate(self.tx_num),
+            None => Ok(()),
+        };
+        let res = res.and_then(|_| {
+            let wounded = self.concurrency_mgr.lock().unwrap().is_wounded();
+            match wounded && !self.is_prepared() {
+                true => Err(DbError::LockAbort),
+                false => Ok(()),
+            }
+        });
+        self.abort_on(res)
+    }
+
+    fn check_prepared(&mut self) -> DbResult<()> {
+        match self.is_prepared() {
+            true => Ok(()),
+            false => Err(DbError::NotPrepared(self.tx_num)),
+        }
+    }
+
+    fn check_not_finished(&mut self) -> DbResult<()> {
+        match self.is_finished() {
+            true => Err(DbError::Finished(self.tx_num)),
+            false => Ok(()),
+        }
+    }
+
+    // A prepared transaction must not change anything, though rolling it
+    // back still undoes its changes.
+    fn check_not_prepared(&mut self) -> DbResult<()> {
+        if self.is_prepared() && !*self.undoing.lock().unwrap() {
+            return Err(DbError::AlreadyPrepared(self.tx_num));
+        }
+        Ok(())
+    }
+
+    fn truncate_savepoints(&mut self, name: &str, keep: bool) -> DbResult<()> {
+        let mut savepoints = self.savepoints.lock().unwrap();
+        match savepoints.iter().position(|savepoint| savepoint == name) {
+            Some(idx) => {
+                savepoints.truncate(if keep { idx + 1 } else { idx });
+                Ok(())
+            }
+            None => Err(DbError::NoSavepoint(name.to_string())),
+        }
+    }
+
+    // MVCC readers see their snapshot instead of taking an S lock. Either way
+    // the page is read under a shared latch.
+    fn read<R>(&mut self, blk: &mut BlockId, f: impl FnOnce(&Page) -> DbResult<R>) -> DbResult<R> {
+        match self.version_store.clone() {
+            Some(vs) => {
+                let buffer = self.pinned_buffer(blk)?;
+                vs.read(self.tx_num, blk, &buffer, f)
+            }
+            None => {
+                self.s_lock(blk)?;
+                let latch = self.pinned_buffer(blk)?.lock().unwrap().latch();
+                let page = latch.read().unwrap();
+                f(&page)
+            }
+        }
+    }
+
+    fn s_lock(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        let res = self.concurrency_mgr.lock().unwrap().s_lock(blk);
+        self.abort_on(res)
+    }
+
+    fn x_lock(&mut self, blk: &mut BlockId) -> DbResult<()> {
+        let res = self.concurrency_mgr.lock().unwrap().x_lock(blk);
+        self.abort_on(res)
+    }
+
+    // A refused lock or pin, or a write conflict, rolls the transaction back
+    // before the error is handed on. While it is already being undone the
+    // error is only handed on; rolling back again would come straight back here.
+    fn abort_on(&mut self, res: DbResult<()>) -> DbResult<()> {
+        if let Err(err) = res {
+            if !*self.undoing.lock().unwrap() {
+                self.rollback()?;
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn undoing<R>(&mut self, f: impl FnOnce(&mut Self) -> DbResult<R>) -> DbResult<R> {
+        *self.undoing.lock().unwrap() = true;
+        let res = f(self);
+        *self.undoing.lock().unwrap() = false;
+        res
+    }
+
+    fn pinned_buffer(&mut self, blk: &mut BlockId) -> DbResult<Arc<Mutex<Buffer>>> {
+        self.my_buffers
+            .lock()
+            .unwrap()
+            .get_buffer(blk)
+            .ok_or_else(|| DbError::NotPinned(blk.clone()))
     }
 }
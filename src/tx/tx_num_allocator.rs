@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use crate::{error::DbResult, logging::log_mgr::LogMgr};
+
+use super::recovery::{
+    checkpoint_record::CheckpointRecord,
+    log_record::{LogRecordIterator, CHECKPOINT, NQCKPT},
+    nq_checkpoint_record::NqCheckpointRecord,
+};
+
+// Hands out the transaction numbers of a database, carrying on after the
+// highest one in the log so that numbers are never reused across restarts.
+pub struct TxNumAllocator {
+    last: AtomicI32,
+}
+
+impl TxNumAllocator {
+    pub fn new(last: i32) -> Self {
+        TxNumAllocator {
+            last: AtomicI32::new(last),
+        }
+    }
+
+    // Every number before a checkpoint is covered by the one it records,
+    // so the scan stops at the most recent checkpoint.
+    pub fn resume(lm: &mut LogMgr) -> DbResult<Self> {
+        let mut last = 0;
+        for rec in LogRecordIterator::new(lm.iterator()?) {
+            let rec = rec?;
+            last = last.max(rec.tx_number());
+            match rec.op() {
+                CHECKPOINT => {
+                    let ckpt = rec.downcast_ref::<CheckpointRecord>().unwrap();
+                    last = last.max(ckpt.max_tx_num());
+                    break;
+                }
+                NQCKPT => {
+                    let ckpt = rec.downcast_ref::<NqCheckpointRecord>().unwrap();
+                    last = last.max(ckpt.max_tx_num());
+                    break;
+                }
+                _ => {}
+            }
+        }
+        Ok(Self::new(last))
+    }
+
+    pub fn next(&self) -> i32 {
+        self.last.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    // The highest number handed out so far.
+    pub fn last(&self) -> i32 {
+        self.last.load(Ordering::SeqCst)
+    }
+}
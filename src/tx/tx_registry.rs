@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::error::DbResult;
+
+#[derive(Default)]
+struct RegistryState {
+    // running transactions and the log segment holding their START record
+    active: BTreeMap<i32, u64>,
+    checkpointing: bool,
+}
+
+// Keeps track of the running transactions so that a checkpoint can hold back
+// new ones and, when quiescent, wait for the running ones to finish.
+#[derive(Default)]
+pub struct TxRegistry {
+    state: Mutex<RegistryState>,
+    cond: Condvar,
+    // held shared by every page update and exclusively by a nonquiescent
+    // checkpoint from its buffer flush to its record
+    updates: RwLock<()>,
+}
+
+impl TxRegistry {
+    pub fn new() -> Self {
+        TxRegistry::default()
+    }
+
+    // Registers a transaction once no checkpoint is running. `begin` writes its
+    // START record and returns what it built along with the record's log segment;
+    // if it fails, nothing is registered.
+    pub fn start<T>(&self, tx_num: i32, begin: impl FnOnce() -> DbResult<(T, u64)>) -> DbResult<T> {
+        let mut state = self.state.lock().unwrap();
+        while state.checkpointing {
+            state = self.cond.wait(state).unwrap();
+        }
+        let (started, segment) = begin()?;
+        state.active.insert(tx_num, segment);
+        Ok(started)
+    }
+
+    pub fn finish(&self, tx_num: i32) {
+        let mut state = self.state.lock().unwrap();
+        state.active.remove(&tx_num);
+        self.cond.notify_all();
+    }
+
+    pub fn active_txs(&self) -> Vec<i32> {
+        let state = self.state.lock().unwrap();
+        state.active.keys().copied().collect()
+    }
+
+    // The oldest log segment a running transaction still needs for rollback.
+    pub fn oldest_segment(&self) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        state.active.values().min().copied()
+    }
+
+    // Stops new transactions from starting and returns the running ones.
+    // A quiescent pause also waits until none are running any more.
+    pub fn pause(&self, quiescent: bool) -> Vec<i32> {
+        let mut state = self.state.lock().unwrap();
+        while state.checkpointing {
+            state = self.cond.wait(state).unwrap();
+        }
+        state.checkpointing = true;
+        while quiescent && !state.active.is_empty() {
+            state = self.cond.wait(state).unwrap();
+        }
+        state.active.keys().copied().collect()
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.checkpointing = false;
+        self.cond.notify_all();
+    }
+
+    // Held while a transaction changes a page and logs the change.
+    pub fn updating(&self) -> RwLockReadGuard<'_, ()> {
+        self.updates.read().unwrap()
+    }
+
+    // Waits for the page updates in progress and holds back new ones, so
+    // that nothing is changed between flushing the buffers and logging the
+    // checkpoint that says they were flushed.
+    pub fn hold_updates(&self) -> RwLockWriteGuard<'_, ()> {
+        self.updates.write().unwrap()
+    }
+}
@@ -4,37 +4,48 @@ use crate::ruspledb::file::file_mgr::FileMgr;
 use crate::ruspledb::logging::log_mgr::LogMgr;
 use ruspledb::buffer::buffer_mgr::BufferMgr;
 use ruspledb::file::block_id::BlockId;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 #[test]
 fn integration_buffer() {
     let db_dir = "./db/buffertest";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
     let block_size = 400;
-    let mut fm = FileMgr::new(db_dir.to_string(), block_size);
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), block_size).unwrap());
     let log_file = "ruspledb.log";
-    let mut lm = LogMgr::new(&mut fm, &mut log_file.to_string());
+    let lm = Arc::new(Mutex::new(
+        LogMgr::new(fm.clone(), &mut log_file.to_string()).unwrap(),
+    ));
     let buffer_size = 3;
-    let mut bm = BufferMgr::new(&mut fm, &mut lm, buffer_size);
+    let bm = BufferMgr::new(fm, lm, buffer_size);
 
-    let mut b1 = bm
+    let buff1 = bm
         .pin(&mut BlockId::new("testfile".to_string(), 1))
         .unwrap();
+    let mut b1 = buff1.lock().unwrap();
     let mut p1 = b1.contents();
-    let n = p1.get_u64(80);
-    p1.set_u64(80, n + 1);
-    // need to set contents directly
-    b1.set_contents(p1);
+    let n = p1.get_u64(80).unwrap();
+    p1.set_u64(80, n + 1).unwrap();
+    drop(p1);
     // enable to flush
     b1.set_modified(1, 0);
     // increasing by 1
     println!("The new value is {}", n + 1);
+    drop(b1);
     // b1 will be chosen as unpinned buffer
-    bm.unpin(&mut b1);
+    bm.unpin(&buff1);
     // this pin will flush b1 to disk
-    let mut b2 = bm
+    let buff2 = bm
         .pin(&mut BlockId::new("testfile".to_string(), 2))
         .unwrap();
+    let mut b2 = buff2.lock().unwrap();
     let mut p2 = b2.contents();
     // will not get written to disk
-    p2.set_u64(80, 9999);
+    p2.set_u64(80, 9999).unwrap();
+    drop(p2);
     b2.set_modified(1, 0);
 }
@@ -3,10 +3,15 @@ extern crate ruspledb;
 use crate::ruspledb::file::file_mgr::FileMgr;
 use crate::ruspledb::logging::log_mgr::LogMgr;
 use ruspledb::buffer::buffer::Buffer;
-use ruspledb::buffer::buffer_mgr::BufferMgr;
+use ruspledb::buffer::buffer_mgr::{BufferMgr, BufferStats, ReplacementPolicy};
+use ruspledb::error::DbError;
 use ruspledb::file::block_id::BlockId;
+use ruspledb::file::page::Page;
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[test]
 fn integration_buffer_mgr() {
@@ -15,13 +20,16 @@ fn integration_buffer_mgr() {
         fs::remove_dir_all(db_dir).unwrap();
     }
     let block_size = 400;
-    let mut fm = FileMgr::new(db_dir.to_string(), block_size);
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), block_size).unwrap());
     let log_file = "ruspledb.log";
-    let mut lm = LogMgr::new(&mut fm, &mut log_file.to_string());
+    let lm = Arc::new(Mutex::new(
+        LogMgr::new(fm.clone(), &mut log_file.to_string()).unwrap(),
+    ));
     let buffer_size = 3;
-    let mut bm = BufferMgr::new(&mut fm, &mut lm, buffer_size);
+    let bm = BufferMgr::new(fm, lm, buffer_size);
+    bm.set_max_time(Duration::from_millis(500));
 
-    let mut buffer: Vec<Buffer> = Vec::with_capacity(6);
+    let mut buffer: Vec<Arc<Mutex<Buffer>>> = Vec::with_capacity(6);
     let mut b0 = BlockId::new("testfile".to_string(), 0);
     let mut b1 = BlockId::new("testfile".to_string(), 1);
     let mut b2 = BlockId::new("testfile".to_string(), 2);
@@ -30,7 +38,7 @@ fn integration_buffer_mgr() {
     buffer.push(bm.pin(&mut b1).unwrap());
     buffer.push(bm.pin(&mut b2).unwrap());
     assert_eq!(bm.available(), 0);
-    bm.unpin(&mut buffer[1]);
+    bm.unpin(&buffer[1]);
     assert_eq!(bm.available(), 1);
     // block 0 pinned twice
     buffer.push(bm.pin(&mut b0).unwrap());
@@ -42,14 +50,237 @@ fn integration_buffer_mgr() {
     println!("Attempting to pin block 3...");
     // will not work; no buffers left
     let res = bm.pin(&mut b3);
-    assert_eq!(res, Err("BufferAbortException".to_string()));
+    assert!(matches!(res, Err(DbError::BufferAbort)));
 
-    bm.unpin(&mut buffer[2]);
+    bm.unpin(&buffer[2]);
     // now this works
     buffer.push(bm.pin(&mut b3).unwrap());
 
-    assert_eq!(buffer[0].block().unwrap().blk_num(), 0);
-    assert_eq!(buffer[3].block().unwrap().blk_num(), 0);
-    assert_eq!(buffer[4].block().unwrap().blk_num(), 1);
-    assert_eq!(buffer[5].block().unwrap().blk_num(), 3);
+    assert_eq!(buffer[0].lock().unwrap().block().unwrap().blk_num(), 0);
+    assert_eq!(buffer[3].lock().unwrap().block().unwrap().blk_num(), 0);
+    assert_eq!(buffer[4].lock().unwrap().block().unwrap().blk_num(), 1);
+    assert_eq!(buffer[5].lock().unwrap().block().unwrap().blk_num(), 3);
+}
+
+// Pins and unpins blocks of a 3-frame pool in turn and returns the blocks
+// that were replaced, in order.
+fn replay(db_dir: &str, policy: ReplacementPolicy, blk_nums: &[u64]) -> (Vec<u64>, BufferStats) {
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let lm = Arc::new(Mutex::new(
+        LogMgr::new(fm.clone(), &mut "ruspledb.log".to_string()).unwrap(),
+    ));
+    let bm = BufferMgr::with_policy(fm, lm, 3, policy);
+    assert_eq!(bm.policy(), policy);
+
+    let mut frames: Vec<(u64, Arc<Mutex<Buffer>>)> = vec![];
+    let mut evicted = vec![];
+    for blk_num in blk_nums {
+        let buffer = bm
+            .pin(&mut BlockId::new("testfile".to_string(), *blk_num))
+            .unwrap();
+        if let Some(pos) = frames.iter().position(|(_, b)| Arc::ptr_eq(b, &buffer)) {
+            let (old, _) = frames.remove(pos);
+            if old != *blk_num {
+                evicted.push(old);
+            }
+        }
+        frames.push((*blk_num, buffer.clone()));
+        bm.unpin(&buffer);
+    }
+    (evicted, bm.stats())
+}
+
+#[test]
+fn integration_replacement_policies() {
+    let blk_nums = [0, 1, 2, 0, 0, 3, 1, 4];
+    let cases = [
+        (ReplacementPolicy::Naive, vec![0, 1, 2, 0, 3, 1]),
+        (ReplacementPolicy::Fifo, vec![0, 1]),
+        (ReplacementPolicy::Lru, vec![1, 2, 0]),
+        (ReplacementPolicy::Clock, vec![0, 2]),
+        (ReplacementPolicy::LruK(2), vec![1, 2, 3]),
+    ];
+    for (i, (policy, expected)) in cases.into_iter().enumerate() {
+        let db_dir = format!("./db/buffermgrtest_{}", i + 2);
+        let (evicted, stats) = replay(&db_dir, policy, &blk_nums);
+        assert_eq!(evicted, expected, "{policy:?}");
+        assert_eq!(stats.evictions, expected.len() as u64);
+        assert_eq!(stats.hits + stats.misses, blk_nums.len() as u64);
+    }
+
+    // only Naive reuses a frame while others are still empty
+    let (_, stats) = replay("./db/buffermgrtest_7", ReplacementPolicy::Naive, &blk_nums);
+    assert_eq!((stats.hits, stats.misses), (1, 7));
+    let (_, stats) = replay("./db/buffermgrtest_8", ReplacementPolicy::Fifo, &blk_nums);
+    assert_eq!((stats.hits, stats.misses), (3, 5));
+    assert_eq!(stats.hit_ratio(), 3.0 / 8.0);
+}
+
+#[test]
+fn integration_pin_waits_for_unpin() {
+    let db_dir = "./db/buffermgrtest_9";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let lm = Arc::new(Mutex::new(
+        LogMgr::new(fm.clone(), &mut "ruspledb.log".to_string()).unwrap(),
+    ));
+    let bm = BufferMgr::new(fm, lm, 2);
+    bm.set_max_time(Duration::from_millis(300));
+    let b0 = bm
+        .pin(&mut BlockId::new("testfile".to_string(), 0))
+        .unwrap();
+    let b1 = bm
+        .pin(&mut BlockId::new("testfile".to_string(), 1))
+        .unwrap();
+
+    // nothing frees up in time
+    let start = Instant::now();
+    let res = bm.pin(&mut BlockId::new("testfile".to_string(), 2));
+    assert!(matches!(res, Err(DbError::BufferAbort)));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    // the waiter gets the frame as soon as another thread unpins it
+    bm.set_max_time(Duration::from_secs(10));
+    let start = Instant::now();
+    thread::scope(|s| {
+        let waiter = s.spawn(|| bm.pin(&mut BlockId::new("testfile".to_string(), 2)));
+        thread::sleep(Duration::from_millis(100));
+        bm.unpin(&b0);
+        let buffer = waiter.join().unwrap().unwrap();
+        assert!(Arc::ptr_eq(&buffer, &b0));
+    });
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(bm.available(), 0);
+    bm.unpin(&b1);
+}
+
+#[test]
+fn integration_concurrent_pins() {
+    let db_dir = "./db/buffermgrtest_10";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let lm = Arc::new(Mutex::new(
+        LogMgr::new(fm.clone(), &mut "ruspledb.log".to_string()).unwrap(),
+    ));
+    let bm = BufferMgr::with_policy(fm, lm, 4, ReplacementPolicy::Clock);
+    // more threads than frames, each pinning a shared block and then one of
+    // its own; whoever waits holds no frame but the shared one
+    thread::scope(|s| {
+        for i in 0..8 {
+            let bm = &bm;
+            s.spawn(move || {
+                for _ in 0..50 {
+                    let shared = bm
+                        .pin(&mut BlockId::new("testfile".to_string(), 0))
+                        .unwrap();
+                    let own = bm
+                        .pin(&mut BlockId::new("testfile".to_string(), i + 1))
+                        .unwrap();
+                    assert_eq!(own.lock().unwrap().block().unwrap().blk_num(), i + 1);
+                    assert_eq!(shared.lock().unwrap().block().unwrap().blk_num(), 0);
+                    bm.unpin(&own);
+                    bm.unpin(&shared);
+                }
+            });
+        }
+    });
+    assert_eq!(bm.available(), 4);
+    let stats = bm.stats();
+    assert_eq!(stats.hits + stats.misses, 800);
+}
+
+#[test]
+fn integration_failed_eviction() {
+    let db_dir = "./db/buffermgrtest_11";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let lm = Arc::new(Mutex::new(
+        LogMgr::new(fm.clone(), &mut "ruspledb.log".to_string()).unwrap(),
+    ));
+    let bm = BufferMgr::new(fm.clone(), lm, 1);
+    bm.set_max_time(Duration::from_millis(500));
+    let mut b0 = BlockId::new("victimfile".to_string(), 0);
+    let buffer = bm.pin(&mut b0).unwrap();
+    {
+        let mut b = buffer.lock().unwrap();
+        b.contents().set_i32(0, 7).unwrap();
+        b.set_modified(1, -1);
+    }
+    bm.unpin(&buffer);
+
+    // the modified block can't be written out while its file is a directory
+    let path = Path::new(db_dir).join("victimfile");
+    let moved = Path::new(db_dir).join("victimfile.moved");
+    fm.close_file("victimfile");
+    fs::rename(&path, &moved).unwrap();
+    fs::create_dir(&path).unwrap();
+    let mut b1 = BlockId::new("testfile".to_string(), 0);
+    assert!(matches!(bm.pin(&mut b1), Err(DbError::Io(_))));
+
+    // so the frame keeps it and it is still found there
+    bm.reset_stats();
+    let buffer = bm.pin(&mut b0).unwrap();
+    assert_eq!(bm.stats().hits, 1);
+    assert_eq!(buffer.lock().unwrap().contents().get_i32(0).unwrap(), 7);
+    bm.unpin(&buffer);
+
+    fs::remove_dir(&path).unwrap();
+    fs::rename(&moved, &path).unwrap();
+    bm.pin(&mut b1).unwrap();
+    let mut page = Page::new(fm.block_size());
+    fm.read(&mut b0, &mut page).unwrap();
+    assert_eq!(page.get_i32(0).unwrap(), 7);
+}
+
+#[test]
+fn integration_pin_during_load() {
+    let db_dir = "./db/buffermgrtest_12";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let lm = Arc::new(Mutex::new(
+        LogMgr::new(fm.clone(), &mut "ruspledb.log".to_string()).unwrap(),
+    ));
+    let bm = BufferMgr::new(fm, lm, 2);
+    let mut b0 = BlockId::new("testfile".to_string(), 0);
+    let mut b1 = BlockId::new("testfile".to_string(), 1);
+    let buff0 = bm.pin(&mut b0).unwrap();
+    let buff1 = bm.pin(&mut b1).unwrap();
+    bm.unpin(&buff1);
+
+    // the pin of block 2 reserves block 1's frame and then waits for it
+    let held = buff1.lock().unwrap();
+    thread::scope(|s| {
+        let loader = s.spawn(|| {
+            bm.pin(&mut BlockId::new("testfile".to_string(), 2))
+                .unwrap()
+        });
+        thread::sleep(Duration::from_millis(100));
+        // meanwhile the pool serves the blocks it has
+        let hit = s.spawn(|| {
+            bm.pin(&mut BlockId::new("testfile".to_string(), 0))
+                .unwrap()
+        });
+        thread::sleep(Duration::from_millis(200));
+        let (hit_done, loader_done) = (hit.is_finished(), loader.is_finished());
+        drop(held);
+        assert!(hit_done);
+        assert!(!loader_done);
+        let buff2 = loader.join().unwrap();
+        assert_eq!(buff2.lock().unwrap().block().unwrap().blk_num(), 2);
+        bm.unpin(&buff2);
+        bm.unpin(&hit.join().unwrap());
+    });
+    bm.unpin(&buff0);
+    assert_eq!(bm.available(), 2);
 }
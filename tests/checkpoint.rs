@@ -0,0 +1,228 @@
+extern crate ruspledb;
+
+mod common;
+
+use common::read_block;
+use ruspledb::file::block_id::BlockId;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::recovery::log_record::{LogRecordIterator, CHECKPOINT, NQCKPT};
+use ruspledb::tx::recovery::nq_checkpoint_record::NqCheckpointRecord;
+use ruspledb::tx::recovery::recovery_mgr::RecoveryMode;
+use ruspledb::tx::recovery::set_int_record::SetIntRecord;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn integration_quiescent_checkpoint() {
+    let db_dir = "./db/checkpointtest";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    {
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        // an unfinished update logged before the checkpoint
+        SetIntRecord::write_to_log(&mut db.log_mgr().lock().unwrap(), 999, &mut blk, 0, 77, 78)
+            .unwrap();
+
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk).unwrap();
+        tx1.set_int(&mut blk, 0, 1, false).unwrap();
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                db.checkpoint().unwrap();
+                done.store(true, Ordering::SeqCst);
+            });
+            // the checkpoint waits for tx1 to finish
+            thread::sleep(Duration::from_millis(300));
+            assert!(!done.load(Ordering::SeqCst));
+            tx1.commit().unwrap();
+        });
+        assert!(done.load(Ordering::SeqCst));
+        assert!(db.active_txs().is_empty());
+
+        let mut recs = LogRecordIterator::new(db.log_mgr().lock().unwrap().iterator().unwrap());
+        assert_eq!(recs.next().unwrap().unwrap().op(), CHECKPOINT);
+    }
+    // recovery stops at the checkpoint and leaves the older record alone
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 1);
+}
+
+#[test]
+fn integration_nq_checkpoint() {
+    let db_dir = "./db/checkpointtest_2";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk0 = BlockId::new("testfile".to_string(), 0);
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    let mut blk2 = BlockId::new("testfile".to_string(), 2);
+    {
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        // an unfinished update logged before any running transaction started
+        SetIntRecord::write_to_log(&mut db.log_mgr().lock().unwrap(), 999, &mut blk2, 0, 77, 78)
+            .unwrap();
+        let mut init = db.new_tx().unwrap();
+        for blk in [&mut blk0, &mut blk1, &mut blk2] {
+            init.pin(blk).unwrap();
+            init.set_int(blk, 0, 1, false).unwrap();
+        }
+        init.commit().unwrap();
+
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk0).unwrap();
+        tx1.set_int(&mut blk0, 0, 10, true).unwrap();
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&mut blk1).unwrap();
+        tx2.set_int(&mut blk1, 0, 20, true).unwrap();
+
+        // running transactions don't hold up a non-quiescent checkpoint
+        db.nq_checkpoint().unwrap();
+        let mut active = db.active_txs();
+        active.sort();
+        let mut expected = vec![tx1.tx_num(), tx2.tx_num()];
+        expected.sort();
+        assert_eq!(active, expected);
+
+        let mut recs = LogRecordIterator::new(db.log_mgr().lock().unwrap().iterator().unwrap());
+        let rec = recs.next().unwrap().unwrap();
+        assert_eq!(rec.op(), NQCKPT);
+        let mut listed = rec
+            .downcast_ref::<NqCheckpointRecord>()
+            .unwrap()
+            .tx_nums()
+            .clone();
+        listed.sort();
+        assert_eq!(listed, expected);
+        drop(recs);
+
+        // the checkpoint flushed the uncommitted changes too
+        assert_eq!(read_block(db_dir, &mut blk0).get_i32(0).unwrap(), 10);
+        assert_eq!(read_block(db_dir, &mut blk1).get_i32(0).unwrap(), 20);
+
+        tx2.commit().unwrap();
+        // tx1 never finishes
+    }
+    // tx1 is undone past the checkpoint, tx2 is kept and the scan
+    // stops at tx1's START before reaching the older record
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk0).get_i32(0).unwrap(), 1);
+    assert_eq!(read_block(db_dir, &mut blk1).get_i32(0).unwrap(), 20);
+    assert_eq!(read_block(db_dir, &mut blk2).get_i32(0).unwrap(), 1);
+}
+
+#[test]
+fn integration_checkpoint_truncates_log() {
+    let db_dir = "./db/checkpointtest_3";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    {
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        let fm = db.file_mgr();
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk).unwrap();
+        tx1.set_int(&mut blk, 0, 1, false).unwrap();
+        tx1.commit().unwrap();
+        assert!(fm.exists("ruspledb.log.000001"));
+
+        db.checkpoint().unwrap();
+        assert!(!fm.exists("ruspledb.log.000001"));
+        assert!(fm.exists("ruspledb.log.000002"));
+
+        // a running transaction keeps the segments from the one with its START
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&mut blk).unwrap();
+        tx2.set_int(&mut blk, 0, 2, true).unwrap();
+        db.nq_checkpoint().unwrap();
+        db.nq_checkpoint().unwrap();
+        assert!(fm.exists("ruspledb.log.000002"));
+        assert!(fm.exists("ruspledb.log.000003"));
+        assert!(fm.exists("ruspledb.log.000004"));
+        tx2.commit().unwrap();
+
+        let mut tx3 = db.new_tx().unwrap();
+        db.nq_checkpoint().unwrap();
+        assert!(!fm.exists("ruspledb.log.000003"));
+        assert!(fm.exists("ruspledb.log.000004"));
+        assert!(fm.exists("ruspledb.log.000005"));
+        tx3.pin(&mut blk).unwrap();
+        tx3.set_int(&mut blk, 0, 3, true).unwrap();
+        // tx3 never finishes
+        db.buffer_mgr().flush_all(tx3.tx_num()).unwrap();
+    }
+    // recovery finds tx3's START in the older segment and keeps tx2's update
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 2);
+}
+
+#[test]
+fn integration_nq_checkpoint_lists_finished_tx() {
+    let db_dir = "./db/checkpointtest_4";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    {
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&mut blk).unwrap();
+        tx.set_int(&mut blk, 0, 5, true).unwrap();
+        let tx_num = tx.tx_num();
+        tx.commit().unwrap();
+
+        // a checkpoint that listed tx just before it committed
+        let log_mgr = db.log_mgr();
+        let mut lm = log_mgr.lock().unwrap();
+        let lsn = NqCheckpointRecord::write_to_log(&mut lm, tx_num, vec![tx_num]).unwrap();
+        lm.flush_with_lsn(lsn).unwrap();
+    }
+    // recovery sees the COMMIT before the checkpoint and leaves tx alone
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 5);
+}
+
+#[test]
+fn integration_nq_checkpoint_undo_redo() {
+    let db_dir = "./db/checkpointtest_5";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk0 = BlockId::new("testfile".to_string(), 0);
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    {
+        let mut db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        db.set_recovery_mode(RecoveryMode::UndoRedo);
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk0).unwrap();
+        // the checkpoint stalls flushing blk1's frame, after blk0's
+        let bm = db.buffer_mgr();
+        let buffer = bm.pin(&mut blk1).unwrap();
+        let held = buffer.lock().unwrap();
+        thread::scope(|s| {
+            s.spawn(|| db.nq_checkpoint().unwrap());
+            thread::sleep(Duration::from_millis(100));
+            let mut blk = blk0.clone();
+            let updater = s.spawn(move || {
+                tx1.set_int(&mut blk, 0, 5, true).unwrap();
+                tx1.commit().unwrap();
+            });
+            thread::sleep(Duration::from_millis(200));
+            // the update waits for the checkpoint record
+            assert!(!updater.is_finished());
+            drop(held);
+        });
+        bm.unpin(&buffer);
+        // the engine goes away with blk0 unwritten
+        assert_eq!(read_block(db_dir, &mut blk0).get_i32(0).unwrap(), 0);
+    }
+    // the commit landed after the checkpoint, so its update is redone
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk0).get_i32(0).unwrap(), 5);
+}
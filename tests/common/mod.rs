@@ -0,0 +1,11 @@
+use ruspledb::file::block_id::BlockId;
+use ruspledb::file::file_mgr::FileMgr;
+use ruspledb::file::page::Page;
+
+// Reads a block straight from disk, bypassing any running engine's buffers.
+pub fn read_block(db_dir: &str, blk: &mut BlockId) -> Page {
+    let fm = FileMgr::new(db_dir.to_string(), 400).unwrap();
+    let mut page = Page::new(fm.block_size());
+    fm.read(blk, &mut page).unwrap();
+    page
+}
@@ -1,14 +1,18 @@
 extern crate ruspledb;
 
-use crate::ruspledb::file::file_mgr::FileMgr;
-use crate::ruspledb::logging::log_mgr::LogMgr;
-use ruspledb::buffer::buffer_mgr::BufferMgr;
+use ruspledb::error::{DbError, DbResult};
 use ruspledb::file::block_id::BlockId;
-use ruspledb::tx::transaction::Transaction;
+use ruspledb::file::file_mgr::FileMgr;
+use ruspledb::file::page::Page;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::concurrency::concurrency_mgr::{ConcurrencyMgr, IsolationLevel};
+use ruspledb::tx::concurrency::lock_table::LockTable;
 use std::fs;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[test]
 fn integration_concurrency() {
@@ -16,68 +20,221 @@ fn integration_concurrency() {
     if Path::new(db_dir).exists() {
         fs::remove_dir_all(db_dir).unwrap();
     }
-    let block_size = 400;
-    let mut fm = FileMgr::new(db_dir.to_string(), block_size);
-    let log_file = "ruspledb.log";
-    let mut lm = LogMgr::new(&mut fm, &mut log_file.to_string());
-    let buffer_size = 8;
-    let mut bm = BufferMgr::new(&mut fm, &mut lm, buffer_size);
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
     let handle = thread::spawn(move || {
-        run_a(&mut fm, &mut lm, &mut bm);
-        run_b(&mut fm, &mut lm, &mut bm);
-        run_c(&mut fm, &mut lm, &mut bm);
+        run_a(&db);
+        run_b(&db);
+        run_c(&db);
     });
     handle.join().unwrap();
 }
 
-fn run_a(fm: &mut FileMgr, lm: &mut LogMgr, bm: &mut BufferMgr) {
-    let mut tx_a = Transaction::new(fm, lm, bm);
+fn run_a(db: &SimpleDB) {
+    let mut tx_a = db.new_tx().unwrap();
     let mut blk1 = BlockId::new("testfile".to_string(), 1);
     let mut blk2 = BlockId::new("testfile".to_string(), 2);
-    tx_a.pin(&mut blk1);
-    tx_a.pin(&mut blk2);
+    tx_a.pin(&mut blk1).unwrap();
+    tx_a.pin(&mut blk2).unwrap();
     println!("Tx A: request slock 1");
-    tx_a.get_int(&mut blk1, 0);
+    tx_a.get_int(&mut blk1, 0).unwrap();
     println!("Tx A: receive slock 1");
     thread::sleep(Duration::from_secs(10));
     println!("Tx A: request slock 2");
-    tx_a.get_int(&mut blk2, 0);
+    tx_a.get_int(&mut blk2, 0).unwrap();
     println!("Tx A: receive slock 2");
-    tx_a.commit();
+    tx_a.commit().unwrap();
     println!("Tx A: commit");
 }
 
-fn run_b(fm: &mut FileMgr, lm: &mut LogMgr, bm: &mut BufferMgr) {
-    let mut tx_b = Transaction::new(fm, lm, bm);
+fn run_b(db: &SimpleDB) {
+    let mut tx_b = db.new_tx().unwrap();
     let mut blk1 = BlockId::new("testfile".to_string(), 1);
     let mut blk2 = BlockId::new("testfile".to_string(), 2);
-    tx_b.pin(&mut blk1);
-    tx_b.pin(&mut blk2);
+    tx_b.pin(&mut blk1).unwrap();
+    tx_b.pin(&mut blk2).unwrap();
     println!("Tx B: request xlock 2");
-    tx_b.set_int(&mut blk2, 0, 0, false);
+    tx_b.set_int(&mut blk2, 0, 0, false).unwrap();
     println!("Tx B: receive xlock 2");
     thread::sleep(Duration::from_secs(10));
     println!("Tx B: request slock 1");
-    tx_b.get_int(&mut blk1, 0);
+    tx_b.get_int(&mut blk1, 0).unwrap();
     println!("Tx B: receive slock 1");
-    tx_b.commit();
+    tx_b.commit().unwrap();
     println!("Tx B: commit");
 }
 
-fn run_c(fm: &mut FileMgr, lm: &mut LogMgr, bm: &mut BufferMgr) {
-    let mut tx_c = Transaction::new(fm, lm, bm);
+fn run_c(db: &SimpleDB) {
+    let mut tx_c = db.new_tx().unwrap();
     let mut blk1 = BlockId::new("testfile".to_string(), 1);
     let mut blk2 = BlockId::new("testfile".to_string(), 2);
-    tx_c.pin(&mut blk1);
-    tx_c.pin(&mut blk2);
+    tx_c.pin(&mut blk1).unwrap();
+    tx_c.pin(&mut blk2).unwrap();
     thread::sleep(Duration::from_secs(5));
     println!("Tx C: request xlock 1");
-    tx_c.set_int(&mut blk1, 0, 0, false);
+    tx_c.set_int(&mut blk1, 0, 0, false).unwrap();
     println!("Tx C: receive xlock 1");
     thread::sleep(Duration::from_secs(10));
     println!("Tx C: request slock 2");
-    tx_c.get_int(&mut blk2, 0);
+    tx_c.get_int(&mut blk2, 0).unwrap();
     println!("Tx C: receive slock 2");
-    tx_c.commit();
+    tx_c.commit().unwrap();
     println!("Tx C: commit");
 }
+
+#[test]
+fn integration_lock_table() {
+    let lock_tbl = Arc::new(LockTable::new());
+    lock_tbl.set_max_time(Duration::from_millis(300));
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut cm_a = ConcurrencyMgr::new(1, IsolationLevel::Serializable, lock_tbl.clone());
+    let mut cm_b = ConcurrencyMgr::new(2, IsolationLevel::Serializable, lock_tbl.clone());
+
+    // S locks are shared
+    cm_a.s_lock(&mut blk).unwrap();
+    cm_b.s_lock(&mut blk).unwrap();
+    // an upgrade waits for the other S lock and gives up
+    let start = Instant::now();
+    assert!(matches!(cm_a.x_lock(&mut blk), Err(DbError::LockAbort)));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    // the upgrade goes through as soon as the other transaction releases
+    lock_tbl.set_max_time(Duration::from_secs(10));
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(200));
+            cm_b.release();
+        });
+        cm_a.x_lock(&mut blk).unwrap();
+    });
+
+    // an X lock keeps out readers until it is released
+    let released = AtomicBool::new(false);
+    thread::scope(|s| {
+        s.spawn(|| {
+            cm_b.s_lock(&mut blk).unwrap();
+            assert!(released.load(Ordering::SeqCst));
+            cm_b.release();
+        });
+        thread::sleep(Duration::from_millis(200));
+        released.store(true, Ordering::SeqCst);
+        cm_a.release();
+    });
+}
+
+#[test]
+fn integration_lock_upgrade_deadlock() {
+    let lock_tbl = Arc::new(LockTable::new());
+    lock_tbl.set_max_time(Duration::from_millis(300));
+    let blk = BlockId::new("testfile".to_string(), 1);
+    // two readers that both want to write can't both win
+    let results: Vec<DbResult<()>> = thread::scope(|s| {
+        let handles: Vec<_> = (1..=2)
+            .map(|tx_num| {
+                let mut cm =
+                    ConcurrencyMgr::new(tx_num, IsolationLevel::Serializable, lock_tbl.clone());
+                let mut blk = blk.clone();
+                s.spawn(move || {
+                    cm.s_lock(&mut blk).unwrap();
+                    thread::sleep(Duration::from_millis(100));
+                    let res = cm.x_lock(&mut blk);
+                    cm.release();
+                    res
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert!(results
+        .iter()
+        .any(|res| matches!(res, Err(DbError::LockAbort))));
+}
+
+#[test]
+fn integration_lock_contention() {
+    let db_dir = "./db/concurrencytest_2";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut tx_a = db.new_tx().unwrap();
+    tx_a.pin(&mut blk).unwrap();
+    tx_a.set_int(&mut blk, 0, 42, true).unwrap();
+
+    let committed = AtomicBool::new(false);
+    thread::scope(|s| {
+        let readers: Vec<_> = (0..3)
+            .map(|_| {
+                let mut tx = db.new_tx().unwrap();
+                let mut blk = blk.clone();
+                let committed = &committed;
+                s.spawn(move || {
+                    tx.pin(&mut blk).unwrap();
+                    // blocks on tx A's X lock
+                    let val = tx.get_int(&mut blk, 0).unwrap();
+                    assert!(committed.load(Ordering::SeqCst));
+                    tx.commit().unwrap();
+                    val
+                })
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(300));
+        committed.store(true, Ordering::SeqCst);
+        tx_a.commit().unwrap();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), 42);
+        }
+    });
+
+    let fm = FileMgr::new(db_dir.to_string(), 400).unwrap();
+    let mut page = Page::new(fm.block_size());
+    fm.read(&mut blk, &mut page).unwrap();
+    assert_eq!(page.get_i32(0).unwrap(), 42);
+}
+
+#[test]
+fn integration_page_latches() {
+    let db_dir = "./db/concurrencytest_5";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let mut init = db.new_tx().unwrap();
+    init.pin(&mut blk).unwrap();
+    init.set_int(&mut blk, 0, 7, false).unwrap();
+    init.commit().unwrap();
+
+    let buffer = db.buffer_mgr().pin(&mut blk).unwrap();
+    let latch = buffer.lock().unwrap().latch();
+    {
+        // readers share the latch and leave the frame itself free
+        let _shared = latch.read().unwrap();
+        assert!(latch.try_read().is_ok());
+        assert!(latch.try_write().is_err());
+        assert!(buffer.try_lock().is_ok());
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&mut blk).unwrap();
+        assert_eq!(tx.get_int(&mut blk, 0).unwrap(), 7);
+        tx.commit().unwrap();
+    }
+
+    // a reader waits for the writer's latch, though no lock stands in its way
+    let released = AtomicBool::new(false);
+    thread::scope(|s| {
+        let exclusive = latch.write().unwrap();
+        let reader = s.spawn(|| {
+            let mut tx = db.new_tx().unwrap();
+            tx.pin(&mut blk.clone()).unwrap();
+            let val = tx.get_int(&mut blk.clone(), 0).unwrap();
+            assert!(released.load(Ordering::SeqCst));
+            tx.commit().unwrap();
+            val
+        });
+        thread::sleep(Duration::from_millis(200));
+        released.store(true, Ordering::SeqCst);
+        drop(exclusive);
+        assert_eq!(reader.join().unwrap(), 7);
+    });
+    db.buffer_mgr().unpin(&buffer);
+}
@@ -0,0 +1,201 @@
+extern crate ruspledb;
+
+use ruspledb::error::{DbError, DbResult};
+use ruspledb::file::block_id::BlockId;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::concurrency::concurrency_mgr::{ConcurrencyMgr, IsolationLevel};
+use ruspledb::tx::concurrency::lock_table::{DeadlockStrategy, LockTable};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn prepare_lock_table(strategy: DeadlockStrategy) -> Arc<LockTable> {
+    let lock_tbl = Arc::new(LockTable::new());
+    lock_tbl.set_strategy(strategy);
+    lock_tbl
+}
+
+// tx1 holds blk1 and tx2 holds blk2, then tx1 asks for blk2 while tx2 asks
+// for blk1. Returns what each request got and how long tx2 took to hear back.
+fn run_deadlock(lock_tbl: Arc<LockTable>) -> (DbResult<()>, DbResult<()>, Duration) {
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    let mut blk2 = BlockId::new("testfile".to_string(), 2);
+    let mut cm1 = ConcurrencyMgr::new(1, IsolationLevel::Serializable, lock_tbl.clone());
+    let mut cm2 = ConcurrencyMgr::new(2, IsolationLevel::Serializable, lock_tbl.clone());
+    cm1.x_lock(&mut blk1).unwrap();
+    cm2.x_lock(&mut blk2).unwrap();
+    thread::scope(|s| {
+        let mut b2 = blk2.clone();
+        let tx1 = s.spawn(move || {
+            let res = cm1.s_lock(&mut b2);
+            cm1.release();
+            res
+        });
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        let res2 = cm2.s_lock(&mut blk1);
+        let elapsed = start.elapsed();
+        cm2.release();
+        (tx1.join().unwrap(), res2, elapsed)
+    })
+}
+
+#[test]
+fn integration_wait_die() {
+    let lock_tbl = prepare_lock_table(DeadlockStrategy::WaitDie);
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut cm1 = ConcurrencyMgr::new(1, IsolationLevel::Serializable, lock_tbl.clone());
+    let mut cm2 = ConcurrencyMgr::new(2, IsolationLevel::Serializable, lock_tbl.clone());
+
+    // the younger transaction dies right away
+    cm1.x_lock(&mut blk).unwrap();
+    let start = Instant::now();
+    assert!(matches!(cm2.s_lock(&mut blk), Err(DbError::LockAbort)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    cm1.release();
+    cm2.release();
+
+    // the older one waits
+    cm2.x_lock(&mut blk).unwrap();
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(200));
+            cm2.release();
+        });
+        cm1.s_lock(&mut blk).unwrap();
+    });
+    cm1.release();
+
+    let (res1, res2, elapsed) = run_deadlock(prepare_lock_table(DeadlockStrategy::WaitDie));
+    assert!(res1.is_ok());
+    assert!(matches!(res2, Err(DbError::LockAbort)));
+    assert!(elapsed < Duration::from_secs(1));
+}
+
+#[test]
+fn integration_wound_wait() {
+    // tx1 wounds tx2, which aborts at its next blocked request
+    let (res1, res2, elapsed) = run_deadlock(prepare_lock_table(DeadlockStrategy::WoundWait));
+    assert!(res1.is_ok());
+    assert!(matches!(res2, Err(DbError::LockAbort)));
+    assert!(elapsed < Duration::from_secs(1));
+
+    // a younger requester just waits
+    let lock_tbl = prepare_lock_table(DeadlockStrategy::WoundWait);
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut cm1 = ConcurrencyMgr::new(1, IsolationLevel::Serializable, lock_tbl.clone());
+    let mut cm2 = ConcurrencyMgr::new(2, IsolationLevel::Serializable, lock_tbl.clone());
+    cm1.x_lock(&mut blk).unwrap();
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(200));
+            cm1.release();
+        });
+        cm2.s_lock(&mut blk).unwrap();
+    });
+    cm2.release();
+}
+
+#[test]
+fn integration_waits_for_graph() {
+    // tx2 closes the cycle, so it is the one that aborts
+    let (res1, res2, elapsed) = run_deadlock(prepare_lock_table(DeadlockStrategy::WaitsForGraph));
+    assert!(res1.is_ok());
+    assert!(matches!(res2, Err(DbError::LockAbort)));
+    assert!(elapsed < Duration::from_secs(1));
+}
+
+#[test]
+fn integration_deadlock_rolls_back() {
+    let db_dir = "./db/deadlocktest";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    db.set_deadlock_strategy(DeadlockStrategy::WaitDie);
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    let mut blk2 = BlockId::new("testfile".to_string(), 2);
+
+    let mut tx1 = db.new_tx().unwrap();
+    let mut tx2 = db.new_tx().unwrap();
+    tx1.pin(&mut blk1).unwrap();
+    tx1.set_int(&mut blk1, 0, 1, true).unwrap();
+    tx2.pin(&mut blk2).unwrap();
+    tx2.set_int(&mut blk2, 0, 2, true).unwrap();
+
+    let res = thread::scope(|s| {
+        let mut blk1 = blk1.clone();
+        s.spawn(move || {
+            tx2.pin(&mut blk1).unwrap();
+            tx2.get_int(&mut blk1, 0)
+        })
+        .join()
+        .unwrap()
+    });
+    // tx2 got a LockAbort and was rolled back
+    assert!(matches!(res, Err(DbError::LockAbort)));
+
+    // so its lock on blk2 is gone and its change undone
+    tx1.pin(&mut blk2).unwrap();
+    assert_eq!(tx1.get_int(&mut blk2, 0).unwrap(), 0);
+    tx1.commit().unwrap();
+}
+
+#[test]
+fn integration_wound_wait_rolls_back_holder() {
+    let db_dir = "./db/deadlocktest_2";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    db.set_deadlock_strategy(DeadlockStrategy::WoundWait);
+    db.set_lock_timeout(Duration::from_secs(5));
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    let mut blk2 = BlockId::new("testfile".to_string(), 2);
+
+    // tx2 is wounded while it holds blk1, and never has to wait itself
+    let mut tx1 = db.new_tx().unwrap();
+    let mut tx2 = db.new_tx().unwrap();
+    tx2.pin(&mut blk1).unwrap();
+    tx2.set_int(&mut blk1, 0, 2, true).unwrap();
+    let res = thread::scope(|s| {
+        let mut blk1 = blk1.clone();
+        let older = s.spawn(move || {
+            tx1.pin(&mut blk1).unwrap();
+            let res = tx1.get_int(&mut blk1, 0);
+            tx1.commit().unwrap();
+            res
+        });
+        thread::sleep(Duration::from_millis(200));
+        // its next lock request aborts it, even though blk2 is free
+        tx2.pin(&mut blk2).unwrap();
+        assert!(matches!(
+            tx2.set_int(&mut blk2, 0, 2, true),
+            Err(DbError::LockAbort)
+        ));
+        older.join().unwrap()
+    });
+    // tx1 got blk1 without waiting out the timeout, and tx2's change is undone
+    assert_eq!(res.unwrap(), 0);
+
+    // a wounded transaction that asks for no other lock can't commit either
+    let mut tx3 = db.new_tx().unwrap();
+    let mut tx4 = db.new_tx().unwrap();
+    tx4.pin(&mut blk1).unwrap();
+    tx4.set_int(&mut blk1, 0, 4, true).unwrap();
+    let res = thread::scope(|s| {
+        let mut blk1 = blk1.clone();
+        let older = s.spawn(move || {
+            tx3.pin(&mut blk1).unwrap();
+            let res = tx3.get_int(&mut blk1, 0);
+            tx3.commit().unwrap();
+            res
+        });
+        thread::sleep(Duration::from_millis(200));
+        assert!(matches!(tx4.commit(), Err(DbError::LockAbort)));
+        older.join().unwrap()
+    });
+    assert_eq!(res.unwrap(), 0);
+}
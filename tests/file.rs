@@ -1,9 +1,12 @@
 extern crate ruspledb;
 
+use crate::ruspledb::error::DbError;
 use crate::ruspledb::file::block_id::BlockId;
-use crate::ruspledb::file::file_mgr::FileMgr;
+use crate::ruspledb::file::db_header::{FORMAT_VERSION, HEADER_FILE};
+use crate::ruspledb::file::file_mgr::{FileMgr, SyncPolicy};
 use crate::ruspledb::file::page::Page;
 use std::fs;
+use std::os::unix::fs::FileExt;
 use std::path::Path;
 
 #[test]
@@ -13,23 +16,232 @@ fn integration_file() {
         fs::remove_dir_all(db_dir).unwrap();
     }
     let block_size = 400;
-    let mut fm = FileMgr::new(db_dir.to_string(), block_size);
+    let fm = FileMgr::new(db_dir.to_string(), block_size).unwrap();
     let mut blk = BlockId::new("testfile".to_string(), 2);
 
     let pos1 = 88;
     let mut p1 = Page::new(fm.block_size());
-    p1.set_string(pos1, "abcdefghijklm".to_string());
+    p1.set_string(pos1, "abcdefghijklm".to_string()).unwrap();
 
-    let size = p1.max_length("abcdefghijklm".len());
+    let size = Page::max_length("abcdefghijklm".len());
     let pos2 = pos1 + size;
-    p1.set_u64(pos2, 345);
+    p1.set_u64(pos2, 345).unwrap();
 
-    fm.write(&mut blk, &mut p1);
+    fm.write(&mut blk, &mut p1).unwrap();
 
     let mut p2 = Page::new(fm.block_size());
 
-    fm.read(&mut blk, &mut p2);
+    fm.read(&mut blk, &mut p2).unwrap();
 
-    assert_eq!(p2.get_string(pos1), "abcdefghijklm");
-    assert_eq!(p2.get_u64(pos2), 345);
+    assert_eq!(p2.get_string(pos1).unwrap(), "abcdefghijklm");
+    assert_eq!(p2.get_u64(pos2).unwrap(), 345);
+}
+
+fn fresh_fm(db_dir: &str, block_size: u64) -> FileMgr {
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    FileMgr::new(db_dir.to_string(), block_size).unwrap()
+}
+
+#[test]
+fn integration_read_past_end() {
+    let fm = fresh_fm("./db/filetest_2", 400);
+    let mut blk = fm.append(&mut "testfile".to_string()).unwrap();
+    let mut page = Page::new(fm.block_size());
+    page.set_u64(392, 7).unwrap();
+    fm.write(&mut blk, &mut page).unwrap();
+
+    // a block past the end of the file reads back as a full block of zeros
+    let mut page = Page::new(fm.block_size());
+    fm.read(&mut BlockId::new("testfile".to_string(), 3), &mut page)
+        .unwrap();
+    assert_eq!(page.contents().len(), 400);
+    assert_eq!(page.get_u64(392).unwrap(), 0);
+    fm.read(&mut blk, &mut page).unwrap();
+    assert_eq!(page.get_u64(392).unwrap(), 7);
+}
+
+#[test]
+fn integration_sync_policy() {
+    let fm = fresh_fm("./db/filetest_3", 400);
+    assert_eq!(fm.sync_policy("testfile"), SyncPolicy::OnCommit);
+    fm.set_sync_policy("ruspledb.log", SyncPolicy::Always);
+    fm.set_default_sync_policy(SyncPolicy::Never);
+    assert_eq!(fm.sync_policy("ruspledb.log.000001"), SyncPolicy::Always);
+    assert_eq!(fm.sync_policy("ruspledb.logfile"), SyncPolicy::Never);
+
+    for policy in [SyncPolicy::Always, SyncPolicy::OnCommit, SyncPolicy::Never] {
+        fm.set_sync_policy("testfile", policy);
+        let mut blk = fm.append(&mut "testfile".to_string()).unwrap();
+        let mut page = Page::new(fm.block_size());
+        page.set_u64(0, blk.blk_num() + 1).unwrap();
+        fm.write(&mut blk, &mut page).unwrap();
+        fm.sync().unwrap();
+        fm.read(&mut blk, &mut page).unwrap();
+        assert_eq!(page.get_u64(0).unwrap(), blk.blk_num() + 1);
+    }
+}
+
+#[test]
+fn integration_direct_io() {
+    let fm = fresh_fm("./db/filetest_4", 400);
+    assert!(fm.set_direct_io(true).is_err());
+
+    let fm = fresh_fm("./db/filetest_4", 4096);
+    fm.set_direct_io(true).unwrap();
+    let mut blk = fm.append(&mut "testfile".to_string()).unwrap();
+    let mut page = Page::new(fm.block_size());
+    page.set_string(4000, "direct".to_string()).unwrap();
+    fm.write(&mut blk, &mut page).unwrap();
+
+    let mut page = Page::new(fm.block_size());
+    fm.read(&mut blk, &mut page).unwrap();
+    assert_eq!(page.get_string(4000).unwrap(), "direct");
+    assert_eq!(fm.length("testfile".to_string()).unwrap(), 1);
+}
+
+#[test]
+fn integration_handle_cache() {
+    let fm = fresh_fm("./db/filetest_5", 400);
+    fm.set_max_open_files(2);
+    let mut page = Page::new(fm.block_size());
+    for i in 0..4 {
+        let mut blk = fm.append(&mut format!("file{i}")).unwrap();
+        page.set_u64(0, i).unwrap();
+        fm.write(&mut blk, &mut page).unwrap();
+    }
+    assert_eq!(fm.open_files(), 2);
+
+    // evicted files are reopened on demand
+    for i in 0..4 {
+        fm.read(&mut BlockId::new(format!("file{i}"), 0), &mut page)
+            .unwrap();
+        assert_eq!(page.get_u64(0).unwrap(), i);
+    }
+    fm.close_file("file3");
+    assert_eq!(fm.open_files(), 1);
+    fm.remove("file2").unwrap();
+    assert_eq!(fm.open_files(), 0);
+    assert_eq!(fm.length("file3".to_string()).unwrap(), 1);
+}
+
+#[test]
+fn integration_temp_files() {
+    let db_dir = "./db/filetest_6";
+    let fm = fresh_fm(db_dir, 400);
+    assert!(fm.is_new());
+    let temp1 = fm.create_temp_file().unwrap();
+    let temp2 = fm.create_temp_file().unwrap();
+    assert_ne!(temp1, temp2);
+    fm.append(&mut temp1.clone()).unwrap();
+    fm.append(&mut "testfile".to_string()).unwrap();
+    // user files that merely start like a temporary
+    fm.append(&mut "temperature.tbl".to_string()).unwrap();
+    fm.append(&mut "temp".to_string()).unwrap();
+    fm.append(&mut "temp1.tbl".to_string()).unwrap();
+
+    // reopening the directory removes the leftover temporaries
+    let fm = FileMgr::new(db_dir.to_string(), 400).unwrap();
+    assert!(!fm.is_new());
+    assert!(!fm.exists(&temp1));
+    assert!(!fm.exists(&temp2));
+    assert!(fm.exists("testfile"));
+    assert!(fm.exists("temperature.tbl"));
+    assert!(fm.exists("temp"));
+    assert!(fm.exists("temp1.tbl"));
+}
+
+#[test]
+fn integration_checksums() {
+    let db_dir = "./db/filetest_7";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = FileMgr::with_checksums(db_dir.to_string(), 400).unwrap();
+    let mut page = Page::new(fm.block_size());
+    for i in 0..3 {
+        let mut blk = fm.append(&mut "testfile".to_string()).unwrap();
+        page.set_string(100, format!("block{i}")).unwrap();
+        fm.write_with_lsn(&mut blk, &mut page, i + 10).unwrap();
+    }
+    assert_eq!(fm.length("testfile".to_string()).unwrap(), 3);
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    assert_eq!(fm.read_with_lsn(&mut blk, &mut page).unwrap(), 11);
+    assert_eq!(page.get_string(100).unwrap(), "block1");
+    assert!(fm.verify().unwrap().is_empty());
+
+    // flip a byte of block 1 behind the file manager's back
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(Path::new(db_dir).join("testfile"))
+        .unwrap();
+    file.write_all_at(b"X", 416 + 100 + 8).unwrap();
+    let res = fm.read(&mut blk, &mut page);
+    assert!(matches!(res, Err(DbError::Corruption(msg)) if msg.contains("block 1 of testfile")));
+    assert_eq!(fm.verify().unwrap(), vec![blk]);
+}
+
+#[test]
+fn integration_db_header() {
+    let db_dir = "./db/filetest_8";
+    let fm = fresh_fm(db_dir, 400);
+    assert!(fm.exists(HEADER_FILE));
+    assert!(!fm.file_names().unwrap().contains(&HEADER_FILE.to_string()));
+    let header = fm.header().clone();
+    assert_eq!(header.version, FORMAT_VERSION);
+    assert_eq!(header.block_size, 400);
+    assert!(!header.has_checksums());
+    drop(fm);
+
+    // the header is kept as it was written
+    let fm = FileMgr::new(db_dir.to_string(), 400).unwrap();
+    assert_eq!(fm.header(), &header);
+    drop(fm);
+
+    let res = FileMgr::new(db_dir.to_string(), 512);
+    assert!(matches!(res, Err(DbError::HeaderMismatch(msg)) if msg.contains("block size 400")));
+    let res = FileMgr::with_checksums(db_dir.to_string(), 400);
+    assert!(matches!(res, Err(DbError::HeaderMismatch(msg)) if msg.contains("without checksums")));
+
+    let fm = FileMgr::open_existing(db_dir.to_string()).unwrap();
+    assert_eq!(fm.block_size(), 400);
+    assert!(!fm.has_checksums());
+    drop(fm);
+
+    // a damaged header isn't trusted
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(Path::new(db_dir).join(HEADER_FILE))
+        .unwrap();
+    file.write_all_at(&[0, 0, 2, 0], 12).unwrap();
+    let res = FileMgr::new(db_dir.to_string(), 400);
+    assert!(matches!(res, Err(DbError::Corruption(_))));
+
+    // data files without a header come from an older format and aren't stamped
+    fs::remove_file(Path::new(db_dir).join(HEADER_FILE)).unwrap();
+    fs::write(Path::new(db_dir).join("testfile"), [0; 400]).unwrap();
+    let res = FileMgr::with_checksums(db_dir.to_string(), 400);
+    assert!(matches!(res, Err(DbError::HeaderMismatch(msg)) if msg.contains("missing")));
+    assert!(!Path::new(db_dir).join(HEADER_FILE).exists());
+
+    // an existing directory with nothing in it is a new database
+    fs::remove_dir_all(db_dir).unwrap();
+    fs::create_dir_all(db_dir).unwrap();
+    let fm = FileMgr::new(db_dir.to_string(), 400).unwrap();
+    assert!(fm.exists(HEADER_FILE));
+}
+
+#[test]
+fn integration_db_header_checksums() {
+    let db_dir = "./db/filetest_9";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    drop(FileMgr::with_checksums(db_dir.to_string(), 1024).unwrap());
+    let res = FileMgr::new(db_dir.to_string(), 1024);
+    assert!(matches!(res, Err(DbError::HeaderMismatch(msg)) if msg.contains("with checksums")));
+    let fm = FileMgr::open_existing(db_dir.to_string()).unwrap();
+    assert_eq!(fm.block_size(), 1024);
+    assert!(fm.has_checksums());
 }
@@ -0,0 +1,162 @@
+extern crate ruspledb;
+
+use ruspledb::error::DbError;
+use ruspledb::file::block_id::BlockId;
+use ruspledb::record::layout::Layout;
+use ruspledb::record::schema::Schema;
+use ruspledb::record::table_scan::TableScan;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::concurrency::concurrency_mgr::IsolationLevel;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+fn prepare_db(db_dir: &str) -> SimpleDB {
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    db.set_lock_timeout(Duration::from_millis(300));
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let mut tx = db.new_tx().unwrap();
+    tx.pin(&mut blk).unwrap();
+    tx.set_int(&mut blk, 0, 1, true).unwrap();
+    tx.commit().unwrap();
+    db
+}
+
+// Returns whether another transaction could write the block without waiting.
+fn try_write(db: &SimpleDB, blk: &BlockId) -> bool {
+    let mut writer = db.new_tx().unwrap();
+    let mut blk = blk.clone();
+    writer.pin(&mut blk).unwrap();
+    match writer.set_int(&mut blk, 0, 2, true) {
+        Ok(()) => {
+            writer.rollback().unwrap();
+            true
+        }
+        Err(err) => {
+            assert!(matches!(err, DbError::LockAbort));
+            false
+        }
+    }
+}
+
+#[test]
+fn integration_read_uncommitted() {
+    let db = prepare_db("./db/isolationtest");
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let mut writer = db.new_tx().unwrap();
+    writer.pin(&mut blk).unwrap();
+    writer.set_int(&mut blk, 0, 2, true).unwrap();
+
+    // a dirty read that doesn't wait for the writer's X lock
+    let mut reader = db
+        .new_tx_with_isolation(IsolationLevel::ReadUncommitted)
+        .unwrap();
+    reader.pin(&mut blk).unwrap();
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 2);
+    writer.rollback().unwrap();
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 1);
+    reader.commit().unwrap();
+}
+
+#[test]
+fn integration_read_committed() {
+    let db = prepare_db("./db/isolationtest_2");
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let mut reader = db
+        .new_tx_with_isolation(IsolationLevel::ReadCommitted)
+        .unwrap();
+    reader.pin(&mut blk).unwrap();
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 1);
+    // the S lock lasts until the end of the statement
+    assert!(!try_write(&db, &blk));
+    reader.end_statement();
+    assert!(try_write(&db, &blk));
+    reader.commit().unwrap();
+}
+
+#[test]
+fn integration_read_committed_scan() {
+    let db = prepare_db("./db/isolationtest_5");
+    let mut schema = Schema::new();
+    schema.add_int_field("A");
+    let layout = Layout::new(&mut schema).unwrap();
+    let mut tx = db.new_tx().unwrap();
+    let mut ts = TableScan::new(&mut tx, "T", layout.clone()).unwrap();
+    loop {
+        ts.insert().unwrap();
+        ts.set_int("A", 1).unwrap();
+        if ts.get_rid().blk_num() == 1 {
+            break;
+        }
+    }
+    ts.close().unwrap();
+    tx.commit().unwrap();
+
+    let mut reader = db
+        .new_tx_with_isolation(IsolationLevel::ReadCommitted)
+        .unwrap();
+    let mut ts = TableScan::new(&mut reader, "T", layout).unwrap();
+    loop {
+        assert!(ts.next().unwrap());
+        if ts.get_rid().blk_num() == 1 {
+            break;
+        }
+    }
+    // moving on to the next block doesn't end the statement
+    let blk0 = BlockId::new("T.tbl".to_string(), 0);
+    assert!(!try_write(&db, &blk0));
+    ts.close().unwrap();
+    assert!(try_write(&db, &blk0));
+    reader.commit().unwrap();
+}
+
+#[test]
+fn integration_repeatable_read() {
+    let db = prepare_db("./db/isolationtest_3");
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let mut reader = db
+        .new_tx_with_isolation(IsolationLevel::RepeatableRead)
+        .unwrap();
+    reader.pin(&mut blk).unwrap();
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 1);
+    reader.end_statement();
+    assert!(!try_write(&db, &blk));
+    // but other transactions may still append to the file
+    let mut appender = db.new_tx().unwrap();
+    assert_eq!(reader.size("testfile").unwrap(), 1);
+    appender.append("testfile").unwrap();
+    appender.commit().unwrap();
+    assert_eq!(reader.size("testfile").unwrap(), 2);
+    reader.commit().unwrap();
+    assert!(try_write(&db, &blk));
+}
+
+#[test]
+fn integration_serializable() {
+    let db = prepare_db("./db/isolationtest_4");
+    let mut reader = db
+        .new_tx_with_isolation(IsolationLevel::Serializable)
+        .unwrap();
+    assert_eq!(reader.size("testfile").unwrap(), 1);
+
+    // the end-of-file lock keeps out appends
+    let mut appender = db.new_tx().unwrap();
+    assert!(matches!(
+        appender.append("testfile"),
+        Err(DbError::LockAbort)
+    ));
+    assert_eq!(reader.size("testfile").unwrap(), 1);
+    reader.commit().unwrap();
+
+    let mut appender = db.new_tx().unwrap();
+    appender.append("testfile").unwrap();
+    appender.commit().unwrap();
+    let mut reader = db
+        .new_tx_with_isolation(IsolationLevel::Serializable)
+        .unwrap();
+    assert_eq!(reader.size("testfile").unwrap(), 2);
+    reader.commit().unwrap();
+}
@@ -1,4 +1,5 @@
 extern crate ruspledb;
+use crate::ruspledb::error::DbError;
 use crate::ruspledb::record::layout::Layout;
 use crate::ruspledb::record::schema::Schema;
 
@@ -14,9 +15,20 @@ fn integration_tx() {
     let mut schema = Schema::new();
     schema.add_string_field("A", 9);
     schema.add_int_field("B");
-    let mut layout = Layout::new(&mut schema);
+    let mut layout = Layout::new(&mut schema).unwrap();
     for field_name in layout.schema().fields().iter_mut() {
-        let offset = layout.offset(field_name);
+        let offset = layout.offset(field_name).unwrap();
         println!("{} has offset {}", field_name, offset);
     }
 }
+
+#[test]
+fn integration_unknown_field() {
+    let mut schema = Schema::new();
+    schema.add_int_field("A");
+    let mut layout = Layout::new(&mut schema).unwrap();
+    assert!(matches!(
+        layout.offset("B"),
+        Err(DbError::SchemaMismatch(_))
+    ));
+}
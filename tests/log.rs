@@ -2,20 +2,19 @@ extern crate ruspledb;
 
 use crate::ruspledb::file::file_mgr::FileMgr;
 use crate::ruspledb::file::page::Page;
-use crate::ruspledb::logging::log_mgr::LogMgr;
+use crate::ruspledb::logging::log_mgr::{LogMgr, LogRetention};
 use bytebuffer::ByteBuffer;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 
 fn print_log_records(lm: &mut LogMgr, msg: String) {
     println!("{}", msg);
-    let mut iter = lm.iterator();
-    while iter.has_next() {
-        let rec = iter.next();
-        let mut page = Page::new_from_buffer(&mut ByteBuffer::from_vec(rec));
-        let s = page.get_string(0);
-        let npos = page.max_length(s.len());
-        let val = page.get_u32(npos);
+    for rec in lm.iterator().unwrap() {
+        let page = Page::new_from_buffer(&mut ByteBuffer::from_vec(rec.unwrap()));
+        let s = page.get_string(0).unwrap();
+        let npos = Page::max_length(s.len());
+        let val = page.get_i32(npos).unwrap();
         println!("[ {s} , {val} ]");
     }
     println!()
@@ -27,23 +26,19 @@ fn create_log_records(lm: &mut LogMgr, start: u64, end: u64) {
         let s = "record".to_string() + &i.to_string();
         let n = i + 100;
         let spos = 0;
-        let npos = spos + max_length(s.len());
+        let npos = spos + Page::max_length(s.len());
         let mut buffer = ByteBuffer::new();
         buffer.resize(npos + 4);
         let mut page = Page::new_from_buffer(&mut buffer);
-        page.set_string(spos, s);
-        page.set_u32(npos, n.try_into().unwrap());
+        page.set_string(spos, s).unwrap();
+        page.set_i32(npos, n.try_into().unwrap()).unwrap();
         // have to `page.buffer` not `buffer`
-        let lsn = lm.append(page.buffer.into_bytes());
+        let lsn = lm.append(page.buffer.into_vec()).unwrap();
         println!("lsn: {lsn}")
     }
     println!()
 }
 
-fn max_length(str_len: usize) -> usize {
-    4 + str_len
-}
-
 // The code creates 70 log records, each consisting of a string and an integer.
 // The code prints the records once after the first 35 have been created and then again after all 70 have been created.
 // If you run the code, you will discover that only 20 records are printed after the first call to printLogRecords.
@@ -60,9 +55,9 @@ fn integration_log() {
         fs::remove_dir_all(db_dir).unwrap();
     }
     let block_size = 400;
-    let mut fm = FileMgr::new(db_dir.to_string(), block_size);
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), block_size).unwrap());
     let log_file = "ruspledb.log";
-    let mut lm = LogMgr::new(&mut fm, &mut log_file.to_string());
+    let mut lm = LogMgr::new(fm, &mut log_file.to_string()).unwrap();
     print_log_records(&mut lm, "The initial empty log file:".to_string());
     println!("done");
     create_log_records(&mut lm, 1, 35);
@@ -71,7 +66,110 @@ fn integration_log() {
     println!("done");
     create_log_records(&mut lm, 36, 70);
     println!("done");
-    lm.flush_with_lsn(65);
+    lm.flush_with_lsn(65).unwrap();
     assert_eq!(lm.get_last_saved_lsn(), 70);
     print_log_records(&mut lm, "The log file now has these records:".to_string());
 }
+
+fn read_numbers(lm: &mut LogMgr) -> Vec<i32> {
+    lm.iterator()
+        .unwrap()
+        .map(|rec| {
+            let page = Page::new_from_buffer(&mut ByteBuffer::from_vec(rec.unwrap()));
+            let s = page.get_string(0).unwrap();
+            let npos = Page::max_length(s.len());
+            page.get_i32(npos).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn integration_log_segments() {
+    let db_dir = "./db/logtest_2";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let log_file = "ruspledb.log";
+    let mut lm = LogMgr::new(fm.clone(), &mut log_file.to_string()).unwrap();
+    assert_eq!(lm.current_segment(), 1);
+    create_log_records(&mut lm, 1, 30);
+    assert_eq!(lm.rotate().unwrap(), 2);
+    create_log_records(&mut lm, 31, 35);
+    assert_eq!(lm.rotate().unwrap(), 3);
+    // an empty segment is skipped
+    assert_eq!(lm.rotate().unwrap(), 4);
+    create_log_records(&mut lm, 36, 40);
+
+    // the iterator walks back across all segments
+    let expected: Vec<i32> = (101..=140).rev().collect();
+    assert_eq!(read_numbers(&mut lm), expected);
+
+    // reopening continues in the newest segment
+    let mut lm = LogMgr::new(fm.clone(), &mut log_file.to_string()).unwrap();
+    assert_eq!(lm.current_segment(), 4);
+    assert_eq!(read_numbers(&mut lm), expected);
+
+    lm.discard_before(2, LogRetention::Archive).unwrap();
+    assert!(!fm.exists("ruspledb.log.000001"));
+    assert!(fm.exists("log_archive/ruspledb.log.000001"));
+    lm.discard_before(4, LogRetention::Truncate).unwrap();
+    assert!(!fm.exists("ruspledb.log.000002"));
+    assert!(!fm.exists("ruspledb.log.000003"));
+    assert!(fm.exists("ruspledb.log.000004"));
+    let expected: Vec<i32> = (136..=140).rev().collect();
+    assert_eq!(read_numbers(&mut lm), expected);
+}
+
+#[test]
+fn integration_log_sync() {
+    let db_dir = "./db/logtest_3";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let mut lm = LogMgr::new(fm, &mut "ruspledb.log".to_string()).unwrap();
+    create_log_records(&mut lm, 1, 5);
+    // iterating writes the records out without syncing them
+    read_numbers(&mut lm);
+    assert_eq!(lm.get_last_saved_lsn(), 5);
+    assert_eq!(lm.get_last_synced_lsn(), 0);
+    lm.flush_with_lsn(3).unwrap();
+    assert_eq!(lm.get_last_synced_lsn(), 5);
+
+    // so does filling a page
+    create_log_records(&mut lm, 6, 30);
+    assert!(lm.get_last_saved_lsn() > 5);
+    lm.flush_with_lsn(6).unwrap();
+    assert!(lm.get_last_synced_lsn() >= 6);
+}
+
+#[test]
+fn integration_log_lsn_resumes() {
+    let db_dir = "./db/logtest_4";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let fm = Arc::new(FileMgr::new(db_dir.to_string(), 400).unwrap());
+    let log_file = "ruspledb.log";
+    let mut lm = LogMgr::new(fm.clone(), &mut log_file.to_string()).unwrap();
+    create_log_records(&mut lm, 1, 30);
+    lm.flush_with_lsn(30).unwrap();
+
+    // numbering carries on where the log left off
+    let mut lm = LogMgr::new(fm.clone(), &mut log_file.to_string()).unwrap();
+    assert_eq!(lm.get_last_saved_lsn(), 30);
+    create_log_records(&mut lm, 31, 31);
+    assert_eq!(lm.get_last_saved_lsn(), 30);
+    lm.flush_with_lsn(31).unwrap();
+
+    // even from a fresh segment once the older ones are gone
+    assert_eq!(lm.rotate().unwrap(), 2);
+    lm.discard_before(2, LogRetention::Truncate).unwrap();
+    let mut lm = LogMgr::new(fm, &mut log_file.to_string()).unwrap();
+    assert_eq!(lm.current_segment(), 2);
+    assert_eq!(lm.get_last_saved_lsn(), 31);
+    let mut buffer = ByteBuffer::new();
+    buffer.resize(4);
+    assert_eq!(lm.append(buffer.into_vec()).unwrap(), 32);
+}
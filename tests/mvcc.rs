@@ -0,0 +1,111 @@
+extern crate ruspledb;
+
+use ruspledb::error::DbError;
+use ruspledb::file::block_id::BlockId;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::concurrency::concurrency_mgr::ConcurrencyMode;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+fn prepare_db(db_dir: &str) -> SimpleDB {
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    db.set_concurrency_mode(ConcurrencyMode::Mvcc);
+    db
+}
+
+#[test]
+fn integration_mvcc_snapshot_reads() {
+    let db = prepare_db("./db/mvcctest");
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut tx1 = db.new_tx().unwrap();
+    tx1.pin(&mut blk).unwrap();
+    tx1.set_int(&mut blk, 0, 1, true).unwrap();
+    tx1.set_string(&mut blk, 40, "one".to_string(), true)
+        .unwrap();
+    tx1.commit().unwrap();
+
+    let mut reader = db.new_tx().unwrap();
+    reader.pin(&mut blk).unwrap();
+    let mut writer = db.new_tx().unwrap();
+    writer.pin(&mut blk).unwrap();
+    writer.set_int(&mut blk, 0, 2, true).unwrap();
+    writer
+        .set_string(&mut blk, 40, "two".to_string(), true)
+        .unwrap();
+    // the writer sees its own changes
+    assert_eq!(writer.get_int(&mut blk, 0).unwrap(), 2);
+
+    // the reader doesn't wait for the writer's X lock
+    let start = Instant::now();
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 1);
+    assert_eq!(reader.get_string(&mut blk, 40).unwrap(), "one");
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    writer.commit().unwrap();
+    // still the same snapshot after the writer committed
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 1);
+    assert_eq!(reader.get_string(&mut blk, 40).unwrap(), "one");
+
+    let mut later = db.new_tx().unwrap();
+    later.pin(&mut blk).unwrap();
+    assert_eq!(later.get_int(&mut blk, 0).unwrap(), 2);
+    assert_eq!(later.get_string(&mut blk, 40).unwrap(), "two");
+    later.commit().unwrap();
+
+    reader.commit().unwrap();
+    // nobody needs the old images any more
+    assert_eq!(db.version_store().unwrap().version_count(), 0);
+}
+
+#[test]
+fn integration_mvcc_rollback() {
+    let db = prepare_db("./db/mvcctest_2");
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut tx1 = db.new_tx().unwrap();
+    tx1.pin(&mut blk).unwrap();
+    tx1.set_int(&mut blk, 0, 1, true).unwrap();
+    tx1.commit().unwrap();
+
+    let mut writer = db.new_tx().unwrap();
+    writer.pin(&mut blk).unwrap();
+    writer.set_int(&mut blk, 0, 2, true).unwrap();
+    writer.rollback().unwrap();
+
+    let mut reader = db.new_tx().unwrap();
+    reader.pin(&mut blk).unwrap();
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 1);
+    reader.commit().unwrap();
+    assert_eq!(db.version_store().unwrap().version_count(), 0);
+}
+
+#[test]
+fn integration_mvcc_write_conflict() {
+    let db = prepare_db("./db/mvcctest_3");
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut tx1 = db.new_tx().unwrap();
+    tx1.pin(&mut blk).unwrap();
+    tx1.set_int(&mut blk, 0, 1, true).unwrap();
+    tx1.commit().unwrap();
+
+    let mut slow = db.new_tx().unwrap();
+    slow.pin(&mut blk).unwrap();
+    assert_eq!(slow.get_int(&mut blk, 0).unwrap(), 1);
+
+    let mut fast = db.new_tx().unwrap();
+    fast.pin(&mut blk).unwrap();
+    fast.set_int(&mut blk, 0, 2, true).unwrap();
+    fast.commit().unwrap();
+
+    // slow writes on top of a value it never saw, so the first committer wins
+    slow.set_int(&mut blk, 0, 3, true).unwrap();
+    assert!(matches!(slow.commit(), Err(DbError::WriteConflict)));
+
+    let mut reader = db.new_tx().unwrap();
+    reader.pin(&mut blk).unwrap();
+    assert_eq!(reader.get_int(&mut blk, 0).unwrap(), 2);
+    reader.commit().unwrap();
+}
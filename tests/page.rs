@@ -0,0 +1,182 @@
+extern crate ruspledb;
+
+use crate::ruspledb::error::DbError;
+use crate::ruspledb::file::page::{
+    Date, Page, Timestamp, BOOL_SIZE, DATE_SIZE, F64_SIZE, I16_SIZE, I32_SIZE, I64_SIZE,
+    TIMESTAMP_SIZE,
+};
+use bytebuffer::ByteBuffer;
+use proptest::prelude::*;
+
+const BLOCK_SIZE: u64 = 400;
+
+// offsets that leave room for the widest fixed-size value
+fn offset() -> impl Strategy<Value = usize> {
+    0..(BLOCK_SIZE as usize - I64_SIZE)
+}
+
+proptest! {
+    #[test]
+    fn integration_page_i16(offset in offset(), n: i16) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_i16(offset, n).unwrap();
+        prop_assert_eq!(page.get_i16(offset).unwrap(), n);
+    }
+
+    #[test]
+    fn integration_page_i32(offset in offset(), n: i32) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_i32(offset, n).unwrap();
+        prop_assert_eq!(page.get_i32(offset).unwrap(), n);
+    }
+
+    #[test]
+    fn integration_page_i64(offset in offset(), n: i64) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_i64(offset, n).unwrap();
+        prop_assert_eq!(page.get_i64(offset).unwrap(), n);
+    }
+
+    #[test]
+    fn integration_page_u64(offset in offset(), n: u64) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_u64(offset, n).unwrap();
+        prop_assert_eq!(page.get_u64(offset).unwrap(), n);
+    }
+
+    #[test]
+    fn integration_page_f64(offset in offset(), n: f64) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_f64(offset, n).unwrap();
+        // compare bits so NaN and -0.0 count too
+        prop_assert_eq!(page.get_f64(offset).unwrap().to_bits(), n.to_bits());
+    }
+
+    #[test]
+    fn integration_page_bool(offset in offset(), b: bool) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_bool(offset, b).unwrap();
+        prop_assert_eq!(page.get_bool(offset).unwrap(), b);
+    }
+
+    #[test]
+    fn integration_page_date(offset in offset(), days: i32) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_date(offset, Date(days)).unwrap();
+        prop_assert_eq!(page.get_date(offset).unwrap(), Date(days));
+    }
+
+    #[test]
+    fn integration_page_timestamp(offset in offset(), micros: i64) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_timestamp(offset, Timestamp(micros)).unwrap();
+        prop_assert_eq!(page.get_timestamp(offset).unwrap(), Timestamp(micros));
+    }
+
+    #[test]
+    fn integration_page_string(offset in 0..200usize, s in "\\PC{0,40}") {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_string(offset, s.clone()).unwrap();
+        prop_assert_eq!(page.get_string(offset).unwrap(), s);
+    }
+
+    #[test]
+    fn integration_page_bytes(offset in 0..200usize, bytes in prop::collection::vec(any::<u8>(), 0..100)) {
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_bytes(offset, bytes.clone()).unwrap();
+        prop_assert_eq!(page.get_bytes(offset).unwrap(), bytes);
+    }
+
+    #[test]
+    fn integration_page_fixed_string(offset in 0..200usize, s in "[^\u{0}]{0,10}", extra in 0..10usize) {
+        let width = s.len() + extra;
+        let mut page = Page::new(BLOCK_SIZE);
+        page.set_fixed_string(offset, &s, width).unwrap();
+        prop_assert_eq!(page.get_fixed_string(offset, width).unwrap(), s);
+    }
+
+    // neighbouring values don't overlap
+    #[test]
+    fn integration_page_record(a: i16, b: i32, c: f64, d: bool, e: i32, f: i64, s in "\\PC{0,20}") {
+        let mut page = Page::new(BLOCK_SIZE);
+        let mut pos = 0;
+        page.set_i16(pos, a).unwrap();
+        pos += I16_SIZE;
+        page.set_i32(pos, b).unwrap();
+        pos += I32_SIZE;
+        page.set_f64(pos, c).unwrap();
+        pos += F64_SIZE;
+        page.set_bool(pos, d).unwrap();
+        pos += BOOL_SIZE;
+        page.set_date(pos, Date(e)).unwrap();
+        pos += DATE_SIZE;
+        page.set_timestamp(pos, Timestamp(f)).unwrap();
+        pos += TIMESTAMP_SIZE;
+        page.set_string(pos, s.clone()).unwrap();
+
+        let mut pos = 0;
+        prop_assert_eq!(page.get_i16(pos).unwrap(), a);
+        pos += I16_SIZE;
+        prop_assert_eq!(page.get_i32(pos).unwrap(), b);
+        pos += I32_SIZE;
+        prop_assert_eq!(page.get_f64(pos).unwrap().to_bits(), c.to_bits());
+        pos += F64_SIZE;
+        prop_assert_eq!(page.get_bool(pos).unwrap(), d);
+        pos += BOOL_SIZE;
+        prop_assert_eq!(page.get_date(pos).unwrap(), Date(e));
+        pos += DATE_SIZE;
+        prop_assert_eq!(page.get_timestamp(pos).unwrap(), Timestamp(f));
+        pos += TIMESTAMP_SIZE;
+        prop_assert_eq!(page.get_string(pos).unwrap(), s);
+    }
+}
+
+#[test]
+fn integration_page_big_endian() {
+    let mut page = Page::new(BLOCK_SIZE);
+    page.set_i32(0, 0x0102_0304).unwrap();
+    page.set_string(4, "ab".to_string()).unwrap();
+    assert_eq!(
+        &page.contents().as_bytes()[..10],
+        &[1, 2, 3, 4, 0, 0, 0, 2, b'a', b'b']
+    );
+}
+
+#[test]
+fn integration_page_bad_reads() {
+    let mut page = Page::new(BLOCK_SIZE);
+    assert!(matches!(page.get_i64(396), Err(DbError::Corruption(_))));
+
+    page.set_bool(0, true).unwrap();
+    page.set_i16(1, 2).unwrap();
+    assert!(matches!(page.get_bool(2), Err(DbError::Corruption(_))));
+
+    // a length that runs past the page
+    page.set_i32(10, 1000).unwrap();
+    assert!(matches!(page.get_bytes(10), Err(DbError::Corruption(_))));
+
+    assert!(matches!(
+        page.set_fixed_string(0, "too long", 4),
+        Err(DbError::SchemaMismatch(_))
+    ));
+}
+
+#[test]
+fn integration_page_bad_writes() {
+    // a block's page never grows past the block
+    let mut page = Page::new(BLOCK_SIZE);
+    assert!(matches!(page.set_i64(396, 1), Err(DbError::Overflow(_))));
+    assert!(matches!(
+        page.set_string(390, "too long".to_string()),
+        Err(DbError::Overflow(_))
+    ));
+    assert_eq!(page.contents().len(), BLOCK_SIZE as usize);
+    assert_eq!(page.get_i32(392).unwrap(), 0);
+    page.set_i32(396, 1).unwrap();
+
+    // a page without a block, e.g. for a log record, does
+    let mut rec = Page::new_from_buffer(&mut ByteBuffer::new());
+    rec.set_string(0, "grows".to_string()).unwrap();
+    rec.set_i32(9, 2).unwrap();
+    assert_eq!(rec.contents().len(), 13);
+}
@@ -0,0 +1,231 @@
+extern crate ruspledb;
+
+mod common;
+
+use common::read_block;
+use ruspledb::file::block_id::BlockId;
+use ruspledb::file::file_mgr::FileMgr;
+use ruspledb::file::page::Page;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::recovery::recovery_mgr::RecoveryMode;
+use std::fs;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+#[test]
+fn integration_recovery() {
+    let db_dir = "./db/recoverytest";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    {
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk).unwrap();
+        // The block initially contains unknown bytes,
+        // so don't log those values here.
+        tx1.set_int(&mut blk, 80, 1, false).unwrap();
+        tx1.set_string(&mut blk, 40, "one".to_string(), false)
+            .unwrap();
+        tx1.commit().unwrap();
+
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&mut blk).unwrap();
+        tx2.set_int(&mut blk, 80, 9999, true).unwrap();
+        tx2.set_string(&mut blk, 40, "crash".to_string(), true)
+            .unwrap();
+        // the modified page reaches the disk before tx2 finishes
+        db.buffer_mgr().flush_all(tx2.tx_num()).unwrap();
+        // the engine goes away here without tx2 committing or rolling back
+    }
+    let page = read_block(db_dir, &mut blk);
+    assert_eq!(page.get_i32(80).unwrap(), 9999);
+    assert_eq!(page.get_string(40).unwrap(), "crash");
+
+    // reopening the database undoes tx2
+    let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+    let page = read_block(db_dir, &mut blk);
+    assert_eq!(page.get_i32(80).unwrap(), 1);
+    assert_eq!(page.get_string(40).unwrap(), "one");
+
+    let mut tx3 = db.new_tx().unwrap();
+    tx3.pin(&mut blk).unwrap();
+    assert_eq!(tx3.get_int(&mut blk, 80).unwrap(), 1);
+    assert_eq!(tx3.get_string(&mut blk, 40).unwrap(), "one");
+    tx3.commit().unwrap();
+}
+
+#[test]
+fn integration_recovery_keeps_committed() {
+    let db_dir = "./db/recoverytest_2";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    {
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk).unwrap();
+        tx1.set_int(&mut blk, 0, 1, false).unwrap();
+        tx1.commit().unwrap();
+
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&mut blk).unwrap();
+        tx2.set_int(&mut blk, 0, 2, true).unwrap();
+        tx2.commit().unwrap();
+
+        let mut tx3 = db.new_tx().unwrap();
+        tx3.pin(&mut blk).unwrap();
+        tx3.set_int(&mut blk, 0, 3, true).unwrap();
+        tx3.rollback().unwrap();
+
+        let mut tx4 = db.new_tx().unwrap();
+        tx4.pin(&mut blk).unwrap();
+        tx4.set_int(&mut blk, 0, 4, true).unwrap();
+        db.buffer_mgr().flush_all(tx4.tx_num()).unwrap();
+    }
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 4);
+
+    // only tx4 is undone; tx2 committed and tx3 already rolled back
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 2);
+
+    // recovery stops at the checkpoint it wrote, so a second restart changes nothing
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 2);
+}
+
+#[test]
+fn integration_recovery_undo_redo() {
+    let db_dir = "./db/recoverytest_3";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk0 = BlockId::new("testfile".to_string(), 0);
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    {
+        let mut db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        db.set_recovery_mode(RecoveryMode::UndoRedo);
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk0).unwrap();
+        tx1.pin(&mut blk1).unwrap();
+        tx1.set_int(&mut blk0, 0, 1, false).unwrap();
+        tx1.set_string(&mut blk1, 0, "one".to_string(), false)
+            .unwrap();
+        tx1.commit().unwrap();
+
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&mut blk0).unwrap();
+        tx2.set_int(&mut blk0, 0, 2, true).unwrap();
+        tx2.set_string(&mut blk0, 40, "two".to_string(), true)
+            .unwrap();
+        tx2.commit().unwrap();
+        // only the log was forced
+        assert_eq!(read_block(db_dir, &mut blk0).get_i32(0).unwrap(), 0);
+
+        let mut tx3 = db.new_tx().unwrap();
+        tx3.pin(&mut blk1).unwrap();
+        tx3.set_string(&mut blk1, 0, "three".to_string(), true)
+            .unwrap();
+        db.buffer_mgr().flush_all(tx3.tx_num()).unwrap();
+        // the engine goes away with tx2's page unwritten and tx3's page written
+    }
+    assert_eq!(
+        read_block(db_dir, &mut blk1).get_string(0).unwrap(),
+        "three"
+    );
+
+    // tx2 is redone and tx3 undone
+    SimpleDB::new(db_dir, 400, 3).unwrap();
+    let page = read_block(db_dir, &mut blk0);
+    assert_eq!(page.get_i32(0).unwrap(), 2);
+    assert_eq!(page.get_string(40).unwrap(), "two");
+    assert_eq!(read_block(db_dir, &mut blk1).get_string(0).unwrap(), "one");
+}
+
+#[test]
+fn integration_recovery_torn_page() {
+    let db_dir = "./db/recoverytest_4";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let open_fm = || FileMgr::with_checksums(db_dir.to_string(), 400).unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    {
+        let db = SimpleDB::with_file_mgr(open_fm(), 3).unwrap();
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk).unwrap();
+        tx1.set_int(&mut blk, 80, 1, true).unwrap();
+        tx1.set_string(&mut blk, 300, "tail".to_string(), true)
+            .unwrap();
+        tx1.commit().unwrap();
+
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&mut blk).unwrap();
+        tx2.set_int(&mut blk, 80, 2, true).unwrap();
+        tx2.commit().unwrap();
+
+        let mut tx3 = db.new_tx().unwrap();
+        tx3.pin(&mut blk).unwrap();
+        tx3.set_string(&mut blk, 300, "gone".to_string(), true)
+            .unwrap();
+        tx3.rollback().unwrap();
+    }
+    // the second half of the block's last write never reached the disk
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(Path::new(db_dir).join("testfile"))
+        .unwrap();
+    file.write_all_at(&[0xab; 200], 416 + 216).unwrap();
+    assert_eq!(open_fm().verify().unwrap(), vec![blk.clone()]);
+
+    // recovery rebuilds the block from its image and the logged updates
+    let db = SimpleDB::with_file_mgr(open_fm(), 3).unwrap();
+    let fm = db.file_mgr();
+    assert!(fm.verify().unwrap().is_empty());
+    let mut page = Page::new(fm.block_size());
+    fm.read(&mut blk, &mut page).unwrap();
+    assert_eq!(page.get_i32(80).unwrap(), 2);
+    assert_eq!(page.get_string(300).unwrap(), "tail");
+}
+
+#[test]
+fn integration_recovery_torn_page_rollback_across_checkpoint() {
+    let db_dir = "./db/recoverytest_5";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let open_fm = || FileMgr::with_checksums(db_dir.to_string(), 400).unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    {
+        let db = SimpleDB::with_file_mgr(open_fm(), 3).unwrap();
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&mut blk).unwrap();
+        tx1.set_int(&mut blk, 80, 1, true).unwrap();
+        tx1.commit().unwrap();
+
+        // the checkpoint flushes tx2's first update, so the image logged
+        // with its second one already holds it
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&mut blk).unwrap();
+        tx2.set_int(&mut blk, 80, 7, true).unwrap();
+        db.nq_checkpoint().unwrap();
+        tx2.set_int(&mut blk, 200, 5, true).unwrap();
+        tx2.rollback().unwrap();
+    }
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(Path::new(db_dir).join("testfile"))
+        .unwrap();
+    file.write_all_at(&[0xab; 200], 416 + 216).unwrap();
+
+    // the rollback is replayed from before the checkpoint too
+    let db = SimpleDB::with_file_mgr(open_fm(), 3).unwrap();
+    let fm = db.file_mgr();
+    assert!(fm.verify().unwrap().is_empty());
+    let mut page = Page::new(fm.block_size());
+    fm.read(&mut blk, &mut page).unwrap();
+    assert_eq!(page.get_i32(80).unwrap(), 1);
+    assert_eq!(page.get_i32(200).unwrap(), 0);
+}
@@ -0,0 +1,93 @@
+extern crate ruspledb;
+
+mod common;
+
+use common::read_block;
+use ruspledb::file::block_id::BlockId;
+use ruspledb::server::simple_db::SimpleDB;
+use std::fs;
+use std::path::Path;
+
+fn prepare_db(db_dir: &str) -> SimpleDB {
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    SimpleDB::new(db_dir, 400, 8).unwrap()
+}
+
+#[test]
+fn integration_savepoint() {
+    let db_dir = "./db/savepointtest";
+    let db = prepare_db(db_dir);
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut tx = db.new_tx().unwrap();
+    tx.pin(&mut blk).unwrap();
+    tx.set_int(&mut blk, 0, 1, true).unwrap();
+    tx.set_string(&mut blk, 40, "one".to_string(), true)
+        .unwrap();
+    tx.savepoint("a").unwrap();
+    tx.set_int(&mut blk, 0, 2, true).unwrap();
+    tx.set_string(&mut blk, 40, "two".to_string(), true)
+        .unwrap();
+    tx.savepoint("b").unwrap();
+    tx.set_int(&mut blk, 0, 3, true).unwrap();
+
+    tx.rollback_to("b").unwrap();
+    assert_eq!(tx.get_int(&mut blk, 0).unwrap(), 2);
+    tx.rollback_to("a").unwrap();
+    assert_eq!(tx.get_int(&mut blk, 0).unwrap(), 1);
+    assert_eq!(tx.get_string(&mut blk, 40).unwrap(), "one");
+    // b was taken after a, so it is gone
+    assert!(tx.rollback_to("b").is_err());
+
+    // a is still there and the earlier undo isn't undone again
+    tx.set_int(&mut blk, 0, 4, true).unwrap();
+    tx.rollback_to("a").unwrap();
+    assert_eq!(tx.get_int(&mut blk, 0).unwrap(), 1);
+    assert_eq!(tx.get_string(&mut blk, 40).unwrap(), "one");
+
+    tx.release("a").unwrap();
+    assert!(tx.rollback_to("a").is_err());
+    assert!(tx.release("a").is_err());
+    tx.commit().unwrap();
+    let page = read_block(db_dir, &mut blk);
+    assert_eq!(page.get_i32(0).unwrap(), 1);
+    assert_eq!(page.get_string(40).unwrap(), "one");
+}
+
+#[test]
+fn integration_savepoint_full_rollback() {
+    let db_dir = "./db/savepointtest_2";
+    let db = prepare_db(db_dir);
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    let mut tx = db.new_tx().unwrap();
+    tx.pin(&mut blk).unwrap();
+    tx.set_int(&mut blk, 0, 5, true).unwrap();
+    tx.savepoint("a").unwrap();
+    tx.set_int(&mut blk, 0, 6, true).unwrap();
+    tx.rollback_to("a").unwrap();
+    tx.set_int(&mut blk, 0, 7, true).unwrap();
+    // a full rollback still goes all the way back
+    tx.rollback().unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 0);
+}
+
+#[test]
+fn integration_savepoint_recovery() {
+    let db_dir = "./db/savepointtest_3";
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    {
+        let db = prepare_db(db_dir);
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&mut blk).unwrap();
+        tx.set_int(&mut blk, 0, 5, true).unwrap();
+        tx.savepoint("a").unwrap();
+        tx.set_int(&mut blk, 0, 6, true).unwrap();
+        tx.rollback_to("a").unwrap();
+        db.buffer_mgr().flush_all(tx.tx_num()).unwrap();
+        // the engine goes away before tx finishes
+    }
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 5);
+    SimpleDB::new(db_dir, 400, 8).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 0);
+}
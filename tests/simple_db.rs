@@ -0,0 +1,121 @@
+extern crate ruspledb;
+
+use ruspledb::file::block_id::BlockId;
+use ruspledb::file::file_mgr::FileMgr;
+use ruspledb::file::page::Page;
+use ruspledb::server::simple_db::SimpleDB;
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+#[test]
+fn integration_simple_db() {
+    let db_dir = "./db/simpledbtest";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+
+    let mut tx1 = db.new_tx().unwrap();
+    tx1.pin(&mut blk).unwrap();
+    tx1.set_int(&mut blk, 80, 123, false).unwrap();
+    tx1.set_string(&mut blk, 40, "shared".to_string(), false)
+        .unwrap();
+    // every transaction pins from the same pool
+    assert_eq!(db.buffer_mgr().available(), 7);
+
+    let mut tx2 = db.new_tx().unwrap();
+    tx2.pin(&mut blk).unwrap();
+    assert_eq!(db.buffer_mgr().available(), 7);
+    tx1.commit().unwrap();
+    // tx2 sees the frame tx1 modified once tx1 released its lock
+    assert_eq!(tx2.get_int(&mut blk, 80).unwrap(), 123);
+    assert_eq!(db.buffer_mgr().available(), 7);
+    tx2.commit().unwrap();
+    assert_eq!(db.buffer_mgr().available(), 8);
+
+    // the committed value reached the database file
+    let fm = db.file_mgr();
+    let mut page = Page::new(fm.block_size());
+    fm.read(&mut blk, &mut page).unwrap();
+    assert_eq!(page.get_i32(80).unwrap(), 123);
+    assert_eq!(page.get_string(40).unwrap(), "shared");
+}
+
+#[test]
+fn integration_tx_nums() {
+    let db_dir = "./db/simpledbtest_2";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let last = {
+        let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+        // numbers are unique across threads
+        let mut tx_nums: Vec<i32> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..10)
+                            .map(|_| {
+                                let mut tx = db.new_tx().unwrap();
+                                tx.commit().unwrap();
+                                tx.tx_num()
+                            })
+                            .collect::<Vec<i32>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        tx_nums.sort();
+        tx_nums.dedup();
+        assert_eq!(tx_nums.len(), 40);
+        *tx_nums.last().unwrap()
+    };
+
+    // a restart carries on after the highest number in the log
+    let last = {
+        let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+        let mut tx = db.new_tx().unwrap();
+        // the recovery transaction took the first one
+        assert_eq!(tx.tx_num(), last + 2);
+        tx.commit().unwrap();
+        db.checkpoint().unwrap();
+        last + 2
+    };
+
+    // even when a checkpoint truncated the log
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    let mut tx = db.new_tx().unwrap();
+    assert_eq!(tx.tx_num(), last + 2);
+    tx.commit().unwrap();
+}
+
+#[test]
+fn integration_checksums() {
+    let db_dir = "./db/simpledbtest_3";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    {
+        let fm = FileMgr::with_checksums(db_dir.to_string(), 400).unwrap();
+        let db = SimpleDB::with_file_mgr(fm, 8).unwrap();
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&mut blk).unwrap();
+        tx.set_int(&mut blk, 80, 42, true).unwrap();
+        tx.commit().unwrap();
+    }
+    // recovery reads the log and the data back through their checksums
+    let fm = FileMgr::with_checksums(db_dir.to_string(), 400).unwrap();
+    let db = SimpleDB::with_file_mgr(fm, 8).unwrap();
+    let mut tx = db.new_tx().unwrap();
+    tx.pin(&mut blk).unwrap();
+    assert_eq!(tx.get_int(&mut blk, 80).unwrap(), 42);
+    tx.commit().unwrap();
+    assert!(db.file_mgr().verify().unwrap().is_empty());
+}
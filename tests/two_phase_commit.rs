@@ -0,0 +1,147 @@
+extern crate ruspledb;
+
+mod common;
+
+use common::read_block;
+use ruspledb::error::DbError;
+use ruspledb::file::block_id::BlockId;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::concurrency::concurrency_mgr::{ConcurrencyMgr, IsolationLevel};
+use ruspledb::tx::recovery::recovery_mgr::RecoveryMode;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+fn fresh_dir(db_dir: &str) {
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+}
+
+// Leaves a transaction prepared on blk, changed from 1 to 2, when the engine goes down.
+fn prepare_and_crash(db_dir: &str, blk: &mut BlockId, recovery_mode: RecoveryMode) -> i32 {
+    let mut db = SimpleDB::new(db_dir, 400, 3).unwrap();
+    db.set_recovery_mode(recovery_mode);
+    let mut init = db.new_tx().unwrap();
+    init.pin(blk).unwrap();
+    init.set_int(blk, 0, 1, false).unwrap();
+    init.commit().unwrap();
+
+    let mut tx = db.new_tx().unwrap();
+    tx.pin(blk).unwrap();
+    tx.set_int(blk, 0, 2, true).unwrap();
+    tx.prepare().unwrap();
+    assert!(tx.is_prepared());
+    tx.tx_num()
+}
+
+// Whether another transaction is kept from writing blk.
+fn is_locked(db: &SimpleDB, blk: &mut BlockId) -> bool {
+    db.set_lock_timeout(Duration::from_millis(100));
+    let mut cm = ConcurrencyMgr::new(9999, IsolationLevel::Serializable, db.lock_table());
+    let locked = matches!(cm.x_lock(blk), Err(DbError::LockAbort));
+    cm.release();
+    locked
+}
+
+#[test]
+fn integration_prepare_commit() {
+    let db_dir = "./db/twophasetest";
+    fresh_dir(db_dir);
+    let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+
+    let mut tx1 = db.new_tx().unwrap();
+    let tx_num = tx1.tx_num();
+    assert!(matches!(tx1.commit_prepared(), Err(DbError::NotPrepared(n)) if n == tx_num));
+    tx1.pin(&mut blk).unwrap();
+    tx1.set_int(&mut blk, 0, 5, true).unwrap();
+    tx1.prepare().unwrap();
+    // the locks are kept until the outcome is known
+    assert!(is_locked(&db, &mut blk));
+    tx1.commit_prepared().unwrap();
+    assert!(!is_locked(&db, &mut blk));
+
+    let mut tx2 = db.new_tx().unwrap();
+    tx2.pin(&mut blk).unwrap();
+    tx2.set_int(&mut blk, 0, 6, true).unwrap();
+    tx2.prepare().unwrap();
+    // nothing more can be changed once prepared
+    let tx_num = tx2.tx_num();
+    let prepared = |res| matches!(res, Err(DbError::AlreadyPrepared(n)) if n == tx_num);
+    assert!(prepared(tx2.set_int(&mut blk, 0, 7, true)));
+    assert!(prepared(
+        tx2.set_string(&mut blk, 40, "x".to_string(), true)
+            .map(|_| ())
+    ));
+    assert!(prepared(tx2.append("testfile").map(|_| ())));
+    tx2.rollback_prepared().unwrap();
+
+    let mut tx3 = db.new_tx().unwrap();
+    tx3.pin(&mut blk).unwrap();
+    assert_eq!(tx3.get_int(&mut blk, 0).unwrap(), 5);
+    tx3.commit().unwrap();
+    assert!(db.in_doubt_txs().is_empty());
+}
+
+#[test]
+fn integration_in_doubt_commit() {
+    let db_dir = "./db/twophasetest_2";
+    fresh_dir(db_dir);
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let tx_num = prepare_and_crash(db_dir, &mut blk, RecoveryMode::UndoOnly);
+    {
+        // recovery leaves the prepared change in place, locked
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 2);
+        let mut in_doubt = db.in_doubt_txs();
+        assert_eq!(in_doubt.len(), 1);
+        assert_eq!(in_doubt[0].tx_num(), tx_num);
+        assert!(in_doubt[0].is_prepared());
+        assert!(is_locked(&db, &mut blk));
+
+        in_doubt[0].commit_prepared().unwrap();
+        assert!(db.in_doubt_txs().is_empty());
+        assert!(!is_locked(&db, &mut blk));
+    }
+    let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert!(db.in_doubt_txs().is_empty());
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 2);
+}
+
+#[test]
+fn integration_in_doubt_rollback() {
+    let db_dir = "./db/twophasetest_3";
+    fresh_dir(db_dir);
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let tx_num = prepare_and_crash(db_dir, &mut blk, RecoveryMode::UndoOnly);
+    {
+        // still in doubt after a second restart with no outcome
+        SimpleDB::new(db_dir, 400, 3).unwrap();
+    }
+    {
+        let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+        let mut in_doubt = db.in_doubt_txs();
+        assert_eq!(in_doubt.len(), 1);
+        assert_eq!(in_doubt[0].tx_num(), tx_num);
+        in_doubt[0].rollback_prepared().unwrap();
+        assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 1);
+    }
+    let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert!(db.in_doubt_txs().is_empty());
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 1);
+}
+
+#[test]
+fn integration_in_doubt_undo_redo() {
+    let db_dir = "./db/twophasetest_4";
+    fresh_dir(db_dir);
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    prepare_and_crash(db_dir, &mut blk, RecoveryMode::UndoRedo);
+    // preparing only forced the log, so recovery redoes the change
+    let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+    assert_eq!(read_block(db_dir, &mut blk).get_i32(0).unwrap(), 2);
+    let mut in_doubt = db.in_doubt_txs();
+    assert_eq!(in_doubt.len(), 1);
+    in_doubt[0].commit_prepared().unwrap();
+}
@@ -1,12 +1,12 @@
 extern crate ruspledb;
 
-use crate::ruspledb::file::file_mgr::FileMgr;
-use crate::ruspledb::logging::log_mgr::LogMgr;
-use ruspledb::buffer::buffer_mgr::BufferMgr;
+use ruspledb::error::DbError;
 use ruspledb::file::block_id::BlockId;
-use ruspledb::tx::transaction::Transaction;
+use ruspledb::server::simple_db::SimpleDB;
+use ruspledb::tx::recovery::recovery_mgr::RecoveryMode;
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
 #[test]
 fn integration_tx() {
@@ -14,26 +14,22 @@ fn integration_tx() {
     if Path::new(db_dir).exists() {
         fs::remove_dir_all(db_dir).unwrap();
     }
-    let block_size = 400;
-    let mut fm = FileMgr::new(db_dir.to_string(), block_size);
-    let log_file = "ruspledb.log";
-    let mut lm = LogMgr::new(&mut fm, &mut log_file.to_string());
-    let buffer_size = 8;
-    let mut bm = BufferMgr::new(&mut fm, &mut lm, buffer_size);
-
-    let mut tx1 = Transaction::new(&mut fm, &mut lm, &mut bm);
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+
+    let mut tx1 = db.new_tx().unwrap();
     let mut blk = BlockId::new("testfile".to_string(), 1);
-    tx1.pin(&mut blk);
+    tx1.pin(&mut blk).unwrap();
     // The block initially contains unknown bytes,
     // so don't log those values here.
-    tx1.set_int(&mut blk, 80, 1, false);
-    tx1.set_string(&mut blk, 40, "one".to_string(), false);
-    tx1.commit();
-
-    let mut tx2 = Transaction::new(&mut fm, &mut lm, &mut bm);
-    tx2.pin(&mut blk);
-    let mut i_val = tx2.get_int(&mut blk, 80);
-    let mut s_val = tx2.get_string(&mut blk, 40);
+    tx1.set_int(&mut blk, 80, 1, false).unwrap();
+    tx1.set_string(&mut blk, 40, "one".to_string(), false)
+        .unwrap();
+    tx1.commit().unwrap();
+
+    let mut tx2 = db.new_tx().unwrap();
+    tx2.pin(&mut blk).unwrap();
+    let mut i_val = tx2.get_int(&mut blk, 80).unwrap();
+    let mut s_val = tx2.get_string(&mut blk, 40).unwrap();
     // initial value at location 80
     assert_eq!(i_val, 1);
     // initial value at location 40
@@ -41,33 +37,206 @@ fn integration_tx() {
 
     let new_i_val = i_val + 1;
     let new_s_val = s_val + "!";
-    tx2.set_int(&mut blk, 80, new_i_val, true);
-    tx2.set_string(&mut blk, 40, new_s_val, true);
-    tx2.commit();
+    tx2.set_int(&mut blk, 80, new_i_val, true).unwrap();
+    tx2.set_string(&mut blk, 40, new_s_val, true).unwrap();
+    tx2.commit().unwrap();
 
-    let mut tx3 = Transaction::new(&mut fm, &mut lm, &mut bm);
-    tx3.pin(&mut blk);
+    let mut tx3 = db.new_tx().unwrap();
+    tx3.pin(&mut blk).unwrap();
 
-    i_val = tx3.get_int(&mut blk, 80);
-    s_val = tx3.get_string(&mut blk, 40);
+    i_val = tx3.get_int(&mut blk, 80).unwrap();
+    s_val = tx3.get_string(&mut blk, 40).unwrap();
     // new value at location 80
     assert_eq!(i_val, 2);
     // new value at location 40
     assert_eq!(s_val, "one!");
 
-    tx3.set_int(&mut blk, 80, 9999, true);
-    i_val = tx3.get_int(&mut blk, 80);
+    tx3.set_int(&mut blk, 80, 9999, true).unwrap();
+    i_val = tx3.get_int(&mut blk, 80).unwrap();
     // pre-rollback value at location 80
     assert_eq!(i_val, 9999);
 
     // tx3.commit();
     // TODO: do rollback correctly (when tx3 is commmited, test fails)
-    tx3.rollback();
+    tx3.rollback().unwrap();
 
-    let mut tx4 = Transaction::new(&mut fm, &mut lm, &mut bm);
-    tx4.pin(&mut blk);
+    let mut tx4 = db.new_tx().unwrap();
+    tx4.pin(&mut blk).unwrap();
 
-    i_val = tx4.get_int(&mut blk, 80);
+    i_val = tx4.get_int(&mut blk, 80).unwrap();
     assert_eq!(i_val, 2);
-    tx4.commit();
+    tx4.commit().unwrap();
+}
+
+#[test]
+fn integration_tx_unpinned_block() {
+    let db_dir = "./db/txtest_2";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    let mut tx = db.new_tx().unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    assert!(matches!(
+        tx.get_int(&mut blk, 80),
+        Err(DbError::NotPinned(_))
+    ));
+    assert!(matches!(
+        tx.set_int(&mut blk, 80, 1, true),
+        Err(DbError::NotPinned(_))
+    ));
+    assert!(matches!(tx.unpin(&mut blk), Err(DbError::NotPinned(_))));
+
+    // a block pinned twice stays pinned until both pins are released
+    tx.pin(&mut blk).unwrap();
+    tx.pin(&mut blk).unwrap();
+    tx.unpin(&mut blk).unwrap();
+    assert_eq!(tx.get_int(&mut blk, 80).unwrap(), 0);
+    tx.unpin(&mut blk).unwrap();
+    assert!(matches!(
+        tx.get_int(&mut blk, 80),
+        Err(DbError::NotPinned(_))
+    ));
+    tx.commit().unwrap();
+}
+
+#[test]
+fn integration_tx_rollback_without_frames() {
+    let db_dir = "./db/txtest_3";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 3).unwrap();
+    db.set_buffer_timeout(Duration::from_millis(100));
+    let mut blk = BlockId::new("testfile".to_string(), 0);
+    let mut tx1 = db.new_tx().unwrap();
+    tx1.pin(&mut blk).unwrap();
+    tx1.set_int(&mut blk, 80, 1, true).unwrap();
+    tx1.unpin(&mut blk).unwrap();
+
+    // every frame is taken, so undoing tx1 can't pin its block
+    let mut tx2 = db.new_tx().unwrap();
+    let mut others: Vec<BlockId> = (1..4)
+        .map(|n| BlockId::new("testfile".to_string(), n))
+        .collect();
+    for other in others.iter_mut() {
+        tx2.pin(other).unwrap();
+    }
+    assert!(matches!(tx1.rollback(), Err(DbError::BufferAbort)));
+
+    // once a frame is free the rollback can be finished
+    tx2.unpin(&mut others[0]).unwrap();
+    tx1.rollback().unwrap();
+    tx2.pin(&mut blk).unwrap();
+    assert_eq!(tx2.get_int(&mut blk, 80).unwrap(), 0);
+    tx2.commit().unwrap();
+}
+
+#[test]
+fn integration_tx_write_past_block() {
+    let db_dir = "./db/txtest_4";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    let mut tx = db.new_tx().unwrap();
+    let mut blk = BlockId::new("testfile".to_string(), 1);
+    tx.pin(&mut blk).unwrap();
+    tx.set_int(&mut blk, 80, 1, true).unwrap();
+    assert!(matches!(
+        tx.set_int(&mut blk, 398, 1, true),
+        Err(DbError::Overflow(_))
+    ));
+    assert!(matches!(
+        tx.set_string(&mut blk, 390, "past the end".to_string(), true),
+        Err(DbError::Overflow(_))
+    ));
+    // nothing was logged for the refused writes, so the rollback goes through
+    tx.rollback().unwrap();
+
+    let mut tx = db.new_tx().unwrap();
+    tx.pin(&mut blk).unwrap();
+    assert_eq!(tx.get_int(&mut blk, 80).unwrap(), 0);
+    tx.commit().unwrap();
+}
+
+#[test]
+fn integration_tx_rollback_after_abort() {
+    let db_dir = "./db/txtest_5";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    db.set_lock_timeout(Duration::from_millis(100));
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    let mut blk2 = BlockId::new("testfile".to_string(), 2);
+    let mut tx1 = db.new_tx().unwrap();
+    let mut tx2 = db.new_tx().unwrap();
+    tx1.pin(&mut blk1).unwrap();
+    tx1.set_int(&mut blk1, 80, 1, true).unwrap();
+    tx2.pin(&mut blk2).unwrap();
+    tx2.set_int(&mut blk2, 80, 2, true).unwrap();
+
+    // tx1 times out waiting for blk2 and is rolled back
+    tx1.pin(&mut blk2).unwrap();
+    assert!(matches!(
+        tx1.get_int(&mut blk2, 80),
+        Err(DbError::LockAbort)
+    ));
+    tx2.pin(&mut blk1).unwrap();
+    tx2.set_int(&mut blk1, 80, 5, true).unwrap();
+    tx2.commit().unwrap();
+
+    // rolling tx1 back again must not undo tx2's committed write
+    tx1.rollback().unwrap();
+    assert!(matches!(
+        tx1.get_int(&mut blk1, 80),
+        Err(DbError::Finished(_))
+    ));
+    let mut tx3 = db.new_tx().unwrap();
+    tx3.pin(&mut blk1).unwrap();
+    assert_eq!(tx3.get_int(&mut blk1, 80).unwrap(), 5);
+    tx3.commit().unwrap();
+}
+
+#[test]
+fn integration_tx_commit_after_abort() {
+    let db_dir = "./db/txtest_6";
+    if Path::new(db_dir).exists() {
+        fs::remove_dir_all(db_dir).unwrap();
+    }
+    let mut blk1 = BlockId::new("testfile".to_string(), 1);
+    let mut blk2 = BlockId::new("testfile".to_string(), 2);
+    {
+        let mut db = SimpleDB::new(db_dir, 400, 8).unwrap();
+        db.set_recovery_mode(RecoveryMode::UndoRedo);
+        db.set_lock_timeout(Duration::from_millis(100));
+        let mut tx1 = db.new_tx().unwrap();
+        let mut tx2 = db.new_tx().unwrap();
+        tx1.pin(&mut blk1).unwrap();
+        tx1.set_int(&mut blk1, 80, 1, true).unwrap();
+        tx2.pin(&mut blk2).unwrap();
+        tx2.set_int(&mut blk2, 80, 2, true).unwrap();
+        tx1.pin(&mut blk2).unwrap();
+        assert!(matches!(
+            tx1.get_int(&mut blk2, 80),
+            Err(DbError::LockAbort)
+        ));
+
+        // tx1 was rolled back, so it can neither commit nor be used
+        assert!(matches!(tx1.commit(), Err(DbError::Finished(_))));
+        assert!(matches!(tx1.pin(&mut blk1), Err(DbError::Finished(_))));
+        assert!(matches!(
+            tx1.set_int(&mut blk1, 80, 3, true),
+            Err(DbError::Finished(_))
+        ));
+        tx2.commit().unwrap();
+    }
+
+    // recovery doesn't redo the rolled-back change
+    let db = SimpleDB::new(db_dir, 400, 8).unwrap();
+    let mut tx = db.new_tx().unwrap();
+    tx.pin(&mut blk1).unwrap();
+    assert_eq!(tx.get_int(&mut blk1, 80).unwrap(), 0);
+    tx.commit().unwrap();
 }